@@ -0,0 +1,94 @@
+use std::ops::Index;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// The colour as normalized RGBA with the given `alpha`.
+    pub fn to_rgba(self, alpha: f32) -> [f32; 4] {
+        [
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+            alpha,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Foreground,
+    Background,
+    Indexed(u8),
+    Spec(Rgb),
+}
+
+/// The xterm default values of the 16 ANSI colours.
+pub const ANSI_COLORS: [Rgb; 16] = [
+    Rgb::new(0x00, 0x00, 0x00),
+    Rgb::new(0xcd, 0x00, 0x00),
+    Rgb::new(0x00, 0xcd, 0x00),
+    Rgb::new(0xcd, 0xcd, 0x00),
+    Rgb::new(0x00, 0x00, 0xee),
+    Rgb::new(0xcd, 0x00, 0xcd),
+    Rgb::new(0x00, 0xcd, 0xcd),
+    Rgb::new(0xe5, 0xe5, 0xe5),
+    Rgb::new(0x7f, 0x7f, 0x7f),
+    Rgb::new(0xff, 0x00, 0x00),
+    Rgb::new(0x00, 0xff, 0x00),
+    Rgb::new(0xff, 0xff, 0x00),
+    Rgb::new(0x5c, 0x5c, 0xff),
+    Rgb::new(0xff, 0x00, 0xff),
+    Rgb::new(0x00, 0xff, 0xff),
+    Rgb::new(0xff, 0xff, 0xff),
+];
+
+/// The 256 colour table: the 16 ANSI colours, a 6x6x6 colour cube and a 24 step grey ramp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 256],
+}
+
+impl Palette {
+    pub fn new(ansi: [Rgb; 16]) -> Self {
+        let mut colors = [Rgb::default(); 256];
+
+        colors[..16].copy_from_slice(&ansi);
+
+        for i in 0..216 {
+            let level = |v: usize| if v == 0 { 0 } else { (v * 40 + 55) as u8 };
+
+            colors[16 + i] = Rgb::new(level(i / 36), level(i / 6 % 6), level(i % 6));
+        }
+
+        for i in 0..24 {
+            let level = (i * 10 + 8) as u8;
+
+            colors[232 + i] = Rgb::new(level, level, level);
+        }
+
+        Self { colors }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new(ANSI_COLORS)
+    }
+}
+
+impl Index<u8> for Palette {
+    type Output = Rgb;
+
+    fn index(&self, index: u8) -> &Rgb {
+        &self.colors[index as usize]
+    }
+}
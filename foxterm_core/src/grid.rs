@@ -0,0 +1,801 @@
+use crate::color::Color;
+use bitflags::bitflags;
+use std::{collections::VecDeque, ops::Range};
+use unicode_width::UnicodeWidthChar;
+
+pub const TAB_WIDTH: usize = 8;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Flags: u16 {
+        const BOLD = 1 << 0;
+        const DIM = 1 << 1;
+        const ITALIC = 1 << 2;
+        const UNDERLINE = 1 << 3;
+        const BLINK = 1 << 4;
+        const INVERSE = 1 << 5;
+        const HIDDEN = 1 << 6;
+        const STRIKEOUT = 1 << 7;
+        const WIDE_CHAR = 1 << 8;
+        const WIDE_CHAR_SPACER = 1 << 9;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+}
+
+impl Cell {
+    pub fn new(c: char, fg: Color, bg: Color, flags: Flags) -> Self {
+        Self { c, fg, bg, flags }
+    }
+
+    /// A blank cell carrying only the background of `template`, as used when erasing.
+    pub fn blank(template: &Cell) -> Self {
+        Self::new(' ', Color::Foreground, template.bg, Flags::empty())
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::new(' ', Color::Foreground, Color::Background, Flags::empty())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    pub template: Cell,
+    pub pending_wrap: bool,
+}
+
+impl Cursor {
+    pub fn new(row: usize, col: usize, template: Cell, pending_wrap: bool) -> Self {
+        Self {
+            row,
+            col,
+            template,
+            pending_wrap,
+        }
+    }
+}
+
+pub type Row = Vec<Cell>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClearMode {
+    Below,
+    Above,
+    All,
+    Saved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineClearMode {
+    Right,
+    Left,
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    pub rows: usize,
+    pub cols: usize,
+    pub lines: Vec<Row>,
+    pub cursor: Cursor,
+    pub saved_cursor: Cursor,
+    pub scroll_region: Range<usize>,
+    pub history: VecDeque<Row>,
+    pub history_size: usize,
+    pub display_offset: usize,
+    /// The rows changed since the damage was last reset, which are all that need redrawing.
+    pub damage: Vec<bool>,
+}
+
+impl Grid {
+    /// Creates an empty screen that keeps up to `history_size` lines scrolled off its top.
+    pub fn new(rows: usize, cols: usize, history_size: usize) -> Self {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+
+        Self {
+            rows,
+            cols,
+            lines: vec![vec![Cell::default(); cols]; rows],
+            cursor: Cursor::default(),
+            saved_cursor: Cursor::default(),
+            scroll_region: 0..rows,
+            history: VecDeque::new(),
+            history_size,
+            display_offset: 0,
+            damage: vec![true; rows],
+        }
+    }
+
+    /// Resizes the screen, truncating or padding lines on the right. When shrinking, lines are
+    /// moved into the history as far as needed to keep the cursor on screen, then removed from
+    /// the bottom. When growing, lines are taken back from the history first, scrolling the
+    /// viewport no further back than the history that is left.
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        let blank = Cell::blank(&self.cursor.template);
+
+        for line in self.lines.iter_mut().chain(self.history.iter_mut()) {
+            // A wide character whose spacer is cut off would be drawn over the edge.
+            if line
+                .get(cols)
+                .is_some_and(|cell| cell.flags.contains(Flags::WIDE_CHAR_SPACER))
+            {
+                line[cols - 1] = blank;
+            }
+
+            line.resize(cols, blank);
+        }
+
+        if rows < self.rows {
+            let shift = (self.cursor.row + 1).saturating_sub(rows);
+            let removed = self.lines.drain(..shift).collect::<Vec<_>>();
+
+            self.push_history(removed);
+            self.lines.truncate(rows);
+            self.cursor.row -= shift;
+            self.saved_cursor.row = self.saved_cursor.row.saturating_sub(shift);
+        } else {
+            let shift = (rows - self.rows).min(self.history.len());
+            let restored = self.history.split_off(self.history.len() - shift);
+
+            self.lines.splice(..0, restored);
+            self.lines.resize(rows, vec![blank; cols]);
+            self.cursor.row += shift;
+            self.saved_cursor.row += shift;
+            // The viewport may have been showing lines that are back on screen now.
+            self.display_offset = self.display_offset.min(self.history.len());
+        }
+
+        self.rows = rows;
+        self.cols = cols;
+        self.scroll_region = 0..rows;
+        self.damage = vec![true; rows];
+
+        self.goto(self.cursor.row, self.cursor.col);
+        self.saved_cursor.row = self.saved_cursor.row.min(rows - 1);
+        self.saved_cursor.col = self.saved_cursor.col.min(cols - 1);
+    }
+
+    /// The line shown at `row` of the viewport, which may be in the history when scrolled back.
+    pub fn display_line(&self, row: usize) -> &Row {
+        if row < self.display_offset {
+            &self.history[self.history.len() - self.display_offset + row]
+        } else {
+            &self.lines[row - self.display_offset]
+        }
+    }
+
+    /// Scrolls the viewport `delta` lines back into the history, or forward when negative.
+    pub fn scroll_display(&mut self, delta: isize) {
+        let offset = self.display_offset as isize + delta;
+
+        self.set_display_offset(offset.clamp(0, self.history.len() as isize) as usize);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.set_display_offset(0);
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        self.damage.contains(&true)
+    }
+
+    pub fn damage_row(&mut self, row: usize) {
+        self.damage[row] = true;
+    }
+
+    pub fn damage_rows(&mut self, rows: Range<usize>) {
+        self.damage[rows].fill(true);
+    }
+
+    pub fn damage_all(&mut self) {
+        self.damage.fill(true);
+    }
+
+    pub fn reset_damage(&mut self) {
+        self.damage.fill(false);
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> &Cell {
+        &self.lines[row][col]
+    }
+
+    pub fn cell_mut(&mut self, row: usize, col: usize) -> &mut Cell {
+        self.damage_row(row);
+
+        &mut self.lines[row][col]
+    }
+
+    /// Writes `c` at the cursor using the cursor template, wrapping first if the previous
+    /// write filled the last column. Wide characters take up two cells, the second of which is
+    /// a spacer, and zero width characters are dropped.
+    pub fn write(&mut self, c: char) {
+        let width = c.width().unwrap_or(0).min(self.cols);
+
+        if width == 0 {
+            return;
+        }
+
+        if self.cursor.pending_wrap || self.cursor.col + width > self.cols {
+            self.carriage_return();
+            self.linefeed();
+        }
+
+        let Cursor {
+            row, col, template, ..
+        } = self.cursor;
+
+        self.erase_partial_wide_chars(row, col..col + width);
+
+        if width == 2 {
+            let flags = template.flags | Flags::WIDE_CHAR;
+            let spacer_flags = template.flags | Flags::WIDE_CHAR_SPACER;
+
+            *self.cell_mut(row, col) = Cell::new(c, template.fg, template.bg, flags);
+            *self.cell_mut(row, col + 1) = Cell::new(' ', template.fg, template.bg, spacer_flags);
+        } else {
+            *self.cell_mut(row, col) = Cell { c, ..template };
+        }
+
+        if col + width < self.cols {
+            self.cursor.col += width;
+        } else {
+            self.cursor.pending_wrap = true;
+        }
+    }
+
+    pub fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+
+        if self.cursor.row + 1 == self.scroll_region.end {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    /// Moves the cursor up one line, scrolling the region down when it is at the top margin.
+    pub fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+
+        if self.cursor.row == self.scroll_region.start {
+            self.scroll_down(1);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+    }
+
+    pub fn carriage_return(&mut self) {
+        self.cursor.col = 0;
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn backspace(&mut self) {
+        self.cursor.col = self.cursor.col.saturating_sub(1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn tab(&mut self) {
+        let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+
+        self.cursor.col = next.min(self.cols - 1);
+    }
+
+    /// Moves the cursor to `row` and `col`, clamped to the screen.
+    pub fn goto(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn goto_row(&mut self, row: usize) {
+        self.goto(row, self.cursor.col);
+    }
+
+    pub fn goto_col(&mut self, col: usize) {
+        self.goto(self.cursor.row, col);
+    }
+
+    pub fn move_up(&mut self, n: usize) {
+        self.goto_row(self.cursor.row.saturating_sub(n));
+    }
+
+    pub fn move_down(&mut self, n: usize) {
+        self.goto_row(self.cursor.row.saturating_add(n));
+    }
+
+    pub fn move_forward(&mut self, n: usize) {
+        self.goto_col(self.cursor.col.saturating_add(n));
+    }
+
+    pub fn move_backward(&mut self, n: usize) {
+        self.goto_col(self.cursor.col.saturating_sub(n));
+    }
+
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = self.cursor;
+    }
+
+    /// Restores the cursor saved by [`Grid::save_cursor`], clamped to the current screen size.
+    pub fn restore_cursor(&mut self) {
+        self.cursor = self.saved_cursor;
+        self.goto(self.cursor.row, self.cursor.col);
+    }
+
+    /// Sets the scrolling region to the rows `top..bottom` and homes the cursor. Invalid
+    /// regions reset it to the whole screen.
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows);
+
+        self.scroll_region = if top + 1 < bottom {
+            top..bottom
+        } else {
+            0..self.rows
+        };
+
+        self.goto(0, 0);
+    }
+
+    /// Scrolls the scrolling region up by `n` lines, discarding its top lines.
+    pub fn scroll_up(&mut self, n: usize) {
+        let region = self.scroll_region.clone();
+
+        if region.start == 0 {
+            let n = n.min(region.end);
+            let removed = self.lines[..n].to_vec();
+
+            self.push_history(removed);
+        }
+
+        self.delete_rows(region.start, region, n);
+    }
+
+    /// Scrolls the scrolling region down by `n` lines, discarding its bottom lines.
+    pub fn scroll_down(&mut self, n: usize) {
+        let region = self.scroll_region.clone();
+
+        self.insert_rows(region.start, region, n);
+    }
+
+    pub fn erase_display(&mut self, mode: ClearMode) {
+        let Cursor { row, .. } = self.cursor;
+
+        match mode {
+            ClearMode::Below => {
+                self.erase_line(LineClearMode::Right);
+                self.clear_rows(row + 1..self.rows);
+            }
+            ClearMode::Above => {
+                self.erase_line(LineClearMode::Left);
+                self.clear_rows(0..row);
+            }
+            ClearMode::All => self.clear_rows(0..self.rows),
+            ClearMode::Saved => {
+                self.scroll_to_bottom();
+                self.history.clear();
+            }
+        }
+    }
+
+    pub fn erase_line(&mut self, mode: LineClearMode) {
+        let Cursor { row, col, .. } = self.cursor;
+
+        match mode {
+            LineClearMode::Right => self.clear_cells(row, col..self.cols),
+            LineClearMode::Left => self.clear_cells(row, 0..col + 1),
+            LineClearMode::All => self.clear_cells(row, 0..self.cols),
+        }
+    }
+
+    /// Inserts `n` blank cells at the cursor, shifting the rest of the line right.
+    pub fn insert_chars(&mut self, n: usize) {
+        let Cursor { row, col, .. } = self.cursor;
+        let n = n.min(self.cols - col);
+        let blank = Cell::blank(&self.cursor.template);
+
+        // Both at the cursor and where cells are pushed off the end of the line.
+        self.erase_partial_wide_char(row, col);
+        self.erase_partial_wide_char(row, self.cols - n);
+
+        let line = &mut self.lines[row];
+
+        line[col..].rotate_right(n);
+        line[col..col + n].fill(blank);
+
+        self.damage_row(row);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Deletes `n` cells at the cursor, shifting the rest of the line left.
+    pub fn delete_chars(&mut self, n: usize) {
+        let Cursor { row, col, .. } = self.cursor;
+        let n = n.min(self.cols - col);
+        let blank = Cell::blank(&self.cursor.template);
+
+        self.erase_partial_wide_chars(row, col..col + n);
+
+        let line = &mut self.lines[row];
+
+        line[col..].rotate_left(n);
+        line[self.cols - n..].fill(blank);
+
+        self.damage_row(row);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Erases `n` cells starting at the cursor without moving the rest of the line.
+    pub fn erase_chars(&mut self, n: usize) {
+        let Cursor { row, col, .. } = self.cursor;
+
+        self.clear_cells(row, col..col.saturating_add(n).min(self.cols));
+    }
+
+    /// Inserts `n` blank lines at the cursor when it is inside the scrolling region.
+    pub fn insert_lines(&mut self, n: usize) {
+        let region = self.scroll_region.clone();
+
+        if region.contains(&self.cursor.row) {
+            self.insert_rows(self.cursor.row, region, n);
+            self.carriage_return();
+        }
+    }
+
+    /// Deletes `n` lines at the cursor when it is inside the scrolling region.
+    pub fn delete_lines(&mut self, n: usize) {
+        let region = self.scroll_region.clone();
+
+        if region.contains(&self.cursor.row) {
+            self.delete_rows(self.cursor.row, region, n);
+            self.carriage_return();
+        }
+    }
+
+    /// Saves lines that left the top of the screen, keeping the viewport on the same content
+    /// while scrolled back.
+    fn push_history(&mut self, lines: Vec<Row>) {
+        if self.history_size == 0 {
+            return;
+        }
+
+        for line in lines {
+            if self.history.len() == self.history_size {
+                self.history.pop_front();
+            } else if self.display_offset > 0 {
+                self.set_display_offset(self.display_offset + 1);
+            }
+
+            self.history.push_back(line);
+        }
+    }
+
+    fn insert_rows(&mut self, at: usize, region: Range<usize>, n: usize) {
+        let n = n.min(region.end - at);
+        let blank = Cell::blank(&self.cursor.template);
+        let lines = &mut self.lines[at..region.end];
+
+        lines.rotate_right(n);
+
+        for line in &mut lines[..n] {
+            line.fill(blank);
+        }
+
+        self.damage_rows(at..region.end);
+    }
+
+    fn delete_rows(&mut self, at: usize, region: Range<usize>, n: usize) {
+        let n = n.min(region.end - at);
+        let blank = Cell::blank(&self.cursor.template);
+        let lines = &mut self.lines[at..region.end];
+        let len = lines.len();
+
+        lines.rotate_left(n);
+
+        for line in &mut lines[len - n..] {
+            line.fill(blank);
+        }
+
+        self.damage_rows(at..region.end);
+    }
+
+    fn clear_rows(&mut self, rows: Range<usize>) {
+        for row in rows {
+            self.clear_cells(row, 0..self.cols);
+        }
+    }
+
+    fn clear_cells(&mut self, row: usize, cols: Range<usize>) {
+        let blank = Cell::blank(&self.cursor.template);
+
+        self.erase_partial_wide_chars(row, cols.clone());
+        self.lines[row][cols].fill(blank);
+        self.damage_row(row);
+    }
+
+    /// Erases the wide characters that `cols` of `row` only partly cover, before those cells
+    /// are changed, so that no half of a wide character is left behind.
+    fn erase_partial_wide_chars(&mut self, row: usize, cols: Range<usize>) {
+        self.erase_partial_wide_char(row, cols.start);
+        self.erase_partial_wide_char(row, cols.end);
+    }
+
+    /// Erases the wide character that starts just before `col` of `row` and ends on it, if any.
+    fn erase_partial_wide_char(&mut self, row: usize, col: usize) {
+        if col == 0 || col >= self.cols {
+            return;
+        }
+
+        if self.lines[row][col].flags.contains(Flags::WIDE_CHAR_SPACER) {
+            let blank = Cell::blank(&self.cursor.template);
+
+            *self.cell_mut(row, col - 1) = blank;
+            *self.cell_mut(row, col) = blank;
+        }
+    }
+
+    /// Moves the viewport, damaging all of it since every row now shows a different line.
+    fn set_display_offset(&mut self, offset: usize) {
+        if offset != self.display_offset {
+            self.display_offset = offset;
+            self.damage_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::performer::{
+        tests::{feed, screen, text},
+        Performer,
+    };
+
+    fn history(grid: &Grid) -> Vec<String> {
+        grid.history.iter().map(text).collect()
+    }
+
+    fn viewport(grid: &Grid) -> Vec<String> {
+        (0..grid.rows)
+            .map(|row| text(grid.display_line(row)))
+            .collect()
+    }
+
+    #[test]
+    fn lines_scrolled_off_the_top_go_to_the_history() {
+        let mut performer = Performer::default(2, 3, 2);
+
+        feed(&mut performer, "1\r\n2\r\n3\r\n4\r\n5");
+
+        assert_eq!(screen(&performer.grid), ["4", "5"]);
+        assert_eq!(history(&performer.grid), ["2", "3"]);
+    }
+
+    #[test]
+    fn alternate_screen_has_no_history() {
+        let mut performer = Performer::default(2, 3, 10);
+
+        feed(&mut performer, "\x1b[?1049h1\r\n2\r\n3\x1b[?1049l");
+
+        assert!(performer.grid.history.is_empty());
+        assert!(performer.inactive_grid.history.is_empty());
+    }
+
+    #[test]
+    fn clearing_the_saved_lines() {
+        let mut performer = Performer::default(2, 3, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3\x1b[3J");
+
+        assert!(performer.grid.history.is_empty());
+        assert_eq!(screen(&performer.grid), ["2", "3"]);
+    }
+
+    #[test]
+    fn scrolling_the_viewport() {
+        let mut performer = Performer::default(2, 3, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3\r\n4");
+        performer.grid.scroll_display(1);
+
+        assert_eq!(viewport(&performer.grid), ["2", "3"]);
+
+        performer.grid.scroll_display(100);
+
+        assert_eq!(performer.grid.display_offset, 2);
+        assert_eq!(viewport(&performer.grid), ["1", "2"]);
+
+        performer.grid.scroll_display(-1);
+
+        assert_eq!(viewport(&performer.grid), ["2", "3"]);
+    }
+
+    #[test]
+    fn scrolled_viewport_stays_on_its_lines_until_output_arrives() {
+        let mut performer = Performer::default(2, 3, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3");
+        performer.grid.scroll_display(1);
+        // Output the parser has not handled yet scrolls the screen under the viewport.
+        performer.grid.linefeed();
+
+        assert_eq!(performer.grid.display_offset, 2);
+        assert_eq!(viewport(&performer.grid), ["1", "2"]);
+
+        feed(&mut performer, "x");
+
+        assert_eq!(performer.grid.display_offset, 0);
+    }
+
+    #[test]
+    fn shrinking_keeps_the_cursor_on_screen() {
+        let mut performer = Performer::default(4, 5, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3\r\nabcde");
+        performer.resize(2, 3);
+
+        let grid = &performer.grid;
+
+        assert_eq!(screen(grid), ["3", "abc"]);
+        assert_eq!(history(grid), ["1", "2"]);
+        assert_eq!((grid.cursor.row, grid.cursor.col), (1, 2));
+    }
+
+    #[test]
+    fn shrinking_drops_lines_below_the_cursor() {
+        let mut performer = Performer::default(4, 3, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3\r\n4\x1b[H");
+        performer.resize(2, 3);
+
+        assert_eq!(screen(&performer.grid), ["1", "2"]);
+        assert!(performer.grid.history.is_empty());
+    }
+
+    #[test]
+    fn growing_takes_lines_back_from_the_history() {
+        let mut performer = Performer::default(2, 3, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3\r\n4");
+        performer.resize(3, 3);
+
+        let grid = &performer.grid;
+
+        assert_eq!(screen(grid), ["2", "3", "4"]);
+        assert_eq!(history(grid), ["1"]);
+        assert_eq!((grid.cursor.row, grid.cursor.col), (2, 1));
+
+        performer.resize(5, 3);
+
+        assert_eq!(screen(&performer.grid), ["1", "2", "3", "4", ""]);
+        assert!(performer.grid.history.is_empty());
+    }
+
+    #[test]
+    fn growing_while_scrolled_back_clamps_the_viewport() {
+        let mut performer = Performer::default(4, 10, 10);
+
+        feed(&mut performer, "1\r\n2\r\n3\r\n4\r\n5\r\n6\r\n7");
+        performer.grid.scroll_display(100);
+
+        assert_eq!(performer.grid.display_offset, 3);
+
+        performer.resize(8, 10);
+
+        assert_eq!(performer.grid.display_offset, 0);
+        assert_eq!(
+            viewport(&performer.grid),
+            ["1", "2", "3", "4", "5", "6", "7", ""]
+        );
+
+        performer.resize(5, 10);
+        performer.grid.scroll_display(100);
+        performer.resize(6, 10);
+
+        assert_eq!(performer.grid.display_offset, 1);
+        assert_eq!(viewport(&performer.grid), ["1", "2", "3", "4", "5", "6"]);
+    }
+
+    /// The characters of `row` with wide characters and their spacers marked, as in `[中]`.
+    fn cells(grid: &Grid, row: usize) -> String {
+        grid.lines[row]
+            .iter()
+            .map(|cell| {
+                if cell.flags.contains(Flags::WIDE_CHAR) {
+                    format!("[{}", cell.c)
+                } else if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                    "]".to_owned()
+                } else {
+                    cell.c.to_string()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wide_chars_take_two_cells() {
+        let mut performer = Performer::default(2, 4, 0);
+
+        feed(&mut performer, "ab中中");
+
+        assert_eq!(cells(&performer.grid, 0), "ab[中]");
+        assert_eq!(cells(&performer.grid, 1), "[中]  ");
+    }
+
+    #[test]
+    fn overwriting_either_half_of_a_wide_char_erases_it() {
+        let mut performer = Performer::default(1, 6, 0);
+
+        feed(&mut performer, "ab中\x1b[1;4Hx");
+        assert_eq!(cells(&performer.grid, 0), "ab x  ");
+
+        feed(&mut performer, "\r中\ra");
+        assert_eq!(cells(&performer.grid, 0), "a  x  ");
+
+        feed(&mut performer, "\r中中\x1b[1;2H中");
+        assert_eq!(cells(&performer.grid, 0), " [中]   ");
+    }
+
+    #[test]
+    fn erasing_half_of_a_wide_char_erases_it() {
+        let mut performer = Performer::default(1, 6, 0);
+
+        feed(&mut performer, "中中中\x1b[1;4H\x1b[K");
+        assert_eq!(cells(&performer.grid, 0), "[中]    ");
+
+        feed(&mut performer, "\r中中中\x1b[1;4H\x1b[1K");
+        assert_eq!(cells(&performer.grid, 0), "    [中]");
+
+        feed(&mut performer, "\r中中中\x1b[1;2H\x1b[2X");
+        assert_eq!(cells(&performer.grid, 0), "    [中]");
+    }
+
+    #[test]
+    fn inserting_and_deleting_inside_a_wide_char_erases_it() {
+        let mut performer = Performer::default(1, 6, 0);
+
+        feed(&mut performer, "中中中\x1b[1;4H\x1b[@");
+        assert_eq!(cells(&performer.grid, 0), "[中]    ");
+
+        feed(&mut performer, "\r中中中\x1b[1;4H\x1b[P");
+        assert_eq!(cells(&performer.grid, 0), "[中] [中] ");
+
+        feed(&mut performer, "\r中中中\x1b[1;3H\x1b[3P");
+        assert_eq!(cells(&performer.grid, 0), "[中]    ");
+    }
+
+    #[test]
+    fn narrowing_through_a_wide_char_erases_it() {
+        let mut performer = Performer::default(1, 6, 0);
+
+        feed(&mut performer, "a中");
+        performer.resize(1, 2);
+
+        assert_eq!(cells(&performer.grid, 0), "a ");
+    }
+
+    #[test]
+    fn damage_follows_changes() {
+        let mut performer = Performer::default(3, 3, 0);
+
+        performer.grid.reset_damage();
+        feed(&mut performer, "\x1b[2;2Hx");
+
+        assert_eq!(performer.grid.damage, [true, true, false]);
+
+        performer.grid.reset_damage();
+        feed(&mut performer, "\x1b[?1049h");
+
+        assert_eq!(performer.grid.damage, [true; 3]);
+    }
+}
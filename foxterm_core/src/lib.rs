@@ -0,0 +1,6 @@
+//! The windowing independent core of foxterm: the PTY, VT parser handling and screen state.
+
+pub mod color;
+pub mod grid;
+pub mod performer;
+pub mod pty;
@@ -0,0 +1,612 @@
+use crate::{
+    color::{Color, Rgb},
+    grid::{Cell, ClearMode, Flags, Grid, LineClearMode},
+};
+use bitflags::bitflags;
+use std::mem;
+use vte::{Params, ParamsIter, Parser, Perform};
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Mode: u16 {
+        const ALT_SCREEN = 1 << 0;
+        const APP_CURSOR = 1 << 1;
+        const APP_KEYPAD = 1 << 2;
+    }
+}
+
+pub struct Performer {
+    pub grid: Grid,
+    pub inactive_grid: Grid,
+    pub mode: Mode,
+}
+
+impl Performer {
+    pub fn new(grid: Grid, inactive_grid: Grid, mode: Mode) -> Self {
+        Self {
+            grid,
+            inactive_grid,
+            mode,
+        }
+    }
+
+    /// Creates a performer whose primary screen keeps `history_size` lines of scrollback.
+    pub fn default(rows: usize, cols: usize, history_size: usize) -> Self {
+        Self::new(
+            Grid::new(rows, cols, history_size),
+            Grid::new(rows, cols, 0),
+            Mode::empty(),
+        )
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.grid.resize(rows, cols);
+        self.inactive_grid.resize(rows, cols);
+    }
+
+    /// Switches to the alternate screen, keeping the cursor where it is.
+    pub fn enter_alt_screen(&mut self) {
+        if !self.mode.contains(Mode::ALT_SCREEN) {
+            self.swap_screens();
+        }
+    }
+
+    /// Switches back to the primary screen, keeping the cursor where it is.
+    pub fn exit_alt_screen(&mut self) {
+        if self.mode.contains(Mode::ALT_SCREEN) {
+            self.swap_screens();
+        }
+    }
+
+    fn swap_screens(&mut self) {
+        let cursor = self.grid.cursor;
+
+        mem::swap(&mut self.grid, &mut self.inactive_grid);
+
+        self.mode.toggle(Mode::ALT_SCREEN);
+        self.grid.goto(cursor.row, cursor.col);
+        self.grid.cursor.template = cursor.template;
+        self.grid.damage_all();
+    }
+
+    /// Handles the DEC private modes of `CSI ? ... h` and `CSI ? ... l`.
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        let alt_screen = self.mode.contains(Mode::ALT_SCREEN);
+
+        match (mode, enable) {
+            (1, _) => self.mode.set(Mode::APP_CURSOR, enable),
+            (47, true) | (1047, true) => self.enter_alt_screen(),
+            (47, false) => self.exit_alt_screen(),
+            (1047, false) => {
+                if alt_screen {
+                    self.grid.erase_display(ClearMode::All);
+                }
+
+                self.exit_alt_screen();
+            }
+            (1048, true) => self.grid.save_cursor(),
+            (1048, false) => self.grid.restore_cursor(),
+            (1049, true) if !alt_screen => {
+                self.grid.save_cursor();
+                self.enter_alt_screen();
+                self.grid.erase_display(ClearMode::All);
+            }
+            (1049, false) if alt_screen => {
+                self.exit_alt_screen();
+                self.grid.restore_cursor();
+            }
+            _ => {}
+        }
+    }
+
+    /// Feeds one byte of output to the parser, snapping the viewport back to the bottom. When
+    /// the cursor moves, the rows it left and entered are damaged.
+    pub fn advance_parser(&mut self, parser: &mut Parser, u: u8) {
+        self.grid.scroll_to_bottom();
+
+        let row = self.grid.cursor.row;
+        let col = self.grid.cursor.col;
+
+        parser.advance(self, u);
+
+        if (row, col) != (self.grid.cursor.row, self.grid.cursor.col) {
+            self.grid.damage_row(row);
+            self.grid.damage_row(self.grid.cursor.row);
+        }
+    }
+
+    /// Applies the SGR parameters of a `CSI ... m` sequence to the cursor template.
+    fn set_attributes(&mut self, params: &Params) {
+        let template = &mut self.grid.cursor.template;
+        let mut iter = params.iter();
+
+        if params.is_empty() {
+            *template = Cell::default();
+        }
+
+        while let Some(param) = iter.next() {
+            match param {
+                [0] => *template = Cell::default(),
+                [1] => template.flags.insert(Flags::BOLD),
+                [2] => template.flags.insert(Flags::DIM),
+                [3] => template.flags.insert(Flags::ITALIC),
+                [4, 0] => template.flags.remove(Flags::UNDERLINE),
+                [4, ..] | [21] => template.flags.insert(Flags::UNDERLINE),
+                [5] | [6] => template.flags.insert(Flags::BLINK),
+                [7] => template.flags.insert(Flags::INVERSE),
+                [8] => template.flags.insert(Flags::HIDDEN),
+                [9] => template.flags.insert(Flags::STRIKEOUT),
+                [22] => template.flags.remove(Flags::BOLD | Flags::DIM),
+                [23] => template.flags.remove(Flags::ITALIC),
+                [24] => template.flags.remove(Flags::UNDERLINE),
+                [25] => template.flags.remove(Flags::BLINK),
+                [27] => template.flags.remove(Flags::INVERSE),
+                [28] => template.flags.remove(Flags::HIDDEN),
+                [29] => template.flags.remove(Flags::STRIKEOUT),
+                [n @ 30..=37] => template.fg = Color::Indexed((n - 30) as u8),
+                [38, rest @ ..] => {
+                    if let Some(color) = parse_color(rest, &mut iter) {
+                        template.fg = color;
+                    }
+                }
+                [39] => template.fg = Color::Foreground,
+                [n @ 40..=47] => template.bg = Color::Indexed((n - 40) as u8),
+                [48, rest @ ..] => {
+                    if let Some(color) = parse_color(rest, &mut iter) {
+                        template.bg = color;
+                    }
+                }
+                [49] => template.bg = Color::Background,
+                [n @ 90..=97] => template.fg = Color::Indexed((n - 90 + 8) as u8),
+                [n @ 100..=107] => template.bg = Color::Indexed((n - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Perform for Performer {
+    fn print(&mut self, c: char) {
+        self.grid.write(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\x08' => self.grid.backspace(),
+            b'\t' => self.grid.tab(),
+            b'\n' | b'\x0b' | b'\x0c' => self.grid.linefeed(),
+            b'\r' => self.grid.carriage_return(),
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        match (intermediates, action) {
+            ([b'?'], 'h' | 'l') => {
+                for mode in params.iter() {
+                    self.set_private_mode(mode[0], action == 'h');
+                }
+
+                return;
+            }
+            ([], _) => {}
+            _ => return,
+        }
+
+        let n = param(params, 0, 1) as usize;
+
+        match action {
+            'A' => self.grid.move_up(n),
+            'B' | 'e' => self.grid.move_down(n),
+            'C' | 'a' => self.grid.move_forward(n),
+            'D' => self.grid.move_backward(n),
+            'E' => {
+                self.grid.move_down(n);
+                self.grid.carriage_return();
+            }
+            'F' => {
+                self.grid.move_up(n);
+                self.grid.carriage_return();
+            }
+            'G' | '`' => self.grid.goto_col(n - 1),
+            'H' | 'f' => {
+                let col = param(params, 1, 1) as usize;
+
+                self.grid.goto(n - 1, col - 1);
+            }
+            'd' => self.grid.goto_row(n - 1),
+            'J' => match param(params, 0, 0) {
+                0 => self.grid.erase_display(ClearMode::Below),
+                1 => self.grid.erase_display(ClearMode::Above),
+                2 => self.grid.erase_display(ClearMode::All),
+                3 => self.grid.erase_display(ClearMode::Saved),
+                _ => {}
+            },
+            'K' => match param(params, 0, 0) {
+                0 => self.grid.erase_line(LineClearMode::Right),
+                1 => self.grid.erase_line(LineClearMode::Left),
+                2 => self.grid.erase_line(LineClearMode::All),
+                _ => {}
+            },
+            '@' => self.grid.insert_chars(n),
+            'P' => self.grid.delete_chars(n),
+            'X' => self.grid.erase_chars(n),
+            'L' => self.grid.insert_lines(n),
+            'M' => self.grid.delete_lines(n),
+            'S' => self.grid.scroll_up(n),
+            'T' => self.grid.scroll_down(n),
+            'r' => {
+                let bottom = param(params, 1, self.grid.rows as u16) as usize;
+
+                self.grid.set_scroll_region(n - 1, bottom);
+            }
+            'm' => self.set_attributes(params),
+            's' => self.grid.save_cursor(),
+            'u' => self.grid.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.grid.save_cursor(),
+            ([], b'8') => self.grid.restore_cursor(),
+            ([], b'=') => self.mode.insert(Mode::APP_KEYPAD),
+            ([], b'>') => self.mode.remove(Mode::APP_KEYPAD),
+            ([], b'D') => self.grid.linefeed(),
+            ([], b'E') => {
+                self.grid.carriage_return();
+                self.grid.linefeed();
+            }
+            ([], b'M') => self.grid.reverse_index(),
+            _ => {}
+        }
+    }
+}
+
+/// Parses the extended colour following SGR 38 or 48, given either as colon separated
+/// subparameters in `sub` (`38:2::r:g:b`) or as the following parameters (`38;5;n`).
+fn parse_color(sub: &[u16], iter: &mut ParamsIter) -> Option<Color> {
+    let rgb = |r: u16, g: u16, b: u16| Color::Spec(Rgb::new(r as u8, g as u8, b as u8));
+
+    match sub {
+        [5, n] => return Some(Color::Indexed(*n as u8)),
+        [2, r, g, b] | [2, _, r, g, b] => return Some(rgb(*r, *g, *b)),
+        [_, ..] => return None,
+        [] => {}
+    }
+
+    match iter.next() {
+        Some([5]) => iter.next().map(|n| Color::Indexed(n[0] as u8)),
+        Some([2]) => match iter.by_ref().take(3).collect::<Vec<_>>().as_slice() {
+            [[r], [g], [b]] => Some(rgb(*r, *g, *b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The `index`th parameter of a sequence, or `default` when it is missing or zero.
+fn param(params: &Params, index: usize, default: u16) -> u16 {
+    match params.iter().nth(index) {
+        Some([n, ..]) if *n != 0 => *n,
+        _ => default,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::grid::Row;
+
+    /// Feeds `input` through the parser one byte at a time, as the PTY reader does.
+    pub(crate) fn feed(performer: &mut Performer, input: &str) {
+        let mut parser = Parser::new();
+
+        for &byte in input.as_bytes() {
+            performer.advance_parser(&mut parser, byte);
+        }
+    }
+
+    /// The characters of `line` without trailing blanks, leaving out wide character spacers.
+    pub(crate) fn text(line: &Row) -> String {
+        line.iter()
+            .filter(|cell| !cell.flags.contains(Flags::WIDE_CHAR_SPACER))
+            .map(|cell| cell.c)
+            .collect::<String>()
+            .trim_end()
+            .to_owned()
+    }
+
+    pub(crate) fn screen(grid: &Grid) -> Vec<String> {
+        grid.lines.iter().map(text).collect()
+    }
+
+    fn cursor(performer: &Performer) -> (usize, usize) {
+        (performer.grid.cursor.row, performer.grid.cursor.col)
+    }
+
+    #[test]
+    fn prints_text_into_the_grid() {
+        let mut performer = Performer::default(2, 5, 0);
+
+        feed(&mut performer, "hi");
+
+        assert_eq!(screen(&performer.grid), ["hi", ""]);
+    }
+
+    #[test]
+    fn wraps_at_the_last_column() {
+        let mut performer = Performer::default(3, 5, 0);
+
+        feed(&mut performer, "abcde");
+
+        assert_eq!(cursor(&performer), (0, 4));
+        assert!(performer.grid.cursor.pending_wrap);
+
+        feed(&mut performer, "fg");
+
+        assert_eq!(screen(&performer.grid), ["abcde", "fg", ""]);
+        assert_eq!(cursor(&performer), (1, 2));
+    }
+
+    #[test]
+    fn control_characters() {
+        let mut performer = Performer::default(3, 20, 0);
+
+        feed(&mut performer, "abc\x08\x08x\r\ny\tz");
+
+        assert_eq!(screen(&performer.grid), ["axc", "y       z", ""]);
+        assert_eq!(cursor(&performer), (1, 9));
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let mut performer = Performer::default(5, 10, 0);
+
+        feed(&mut performer, "\x1b[3;4H");
+        assert_eq!(cursor(&performer), (2, 3));
+
+        feed(&mut performer, "\x1b[A\x1b[2C");
+        assert_eq!(cursor(&performer), (1, 5));
+
+        feed(&mut performer, "\x1b[B\x1b[4D");
+        assert_eq!(cursor(&performer), (2, 1));
+
+        feed(&mut performer, "\x1b[E");
+        assert_eq!(cursor(&performer), (3, 0));
+
+        feed(&mut performer, "\x1b[7G\x1b[2F");
+        assert_eq!(cursor(&performer), (1, 0));
+
+        feed(&mut performer, "\x1b[5d\x1b[H");
+        assert_eq!(cursor(&performer), (0, 0));
+
+        feed(&mut performer, "\x1b[99;99H");
+        assert_eq!(cursor(&performer), (4, 9));
+
+        feed(&mut performer, "\x1b[99A\x1b[99D");
+        assert_eq!(cursor(&performer), (0, 0));
+    }
+
+    #[test]
+    fn save_and_restore_cursor() {
+        let mut performer = Performer::default(5, 10, 0);
+
+        feed(&mut performer, "\x1b[2;3H\x1b7\x1b[H\x1b8");
+        assert_eq!(cursor(&performer), (1, 2));
+
+        feed(&mut performer, "\x1b[4;5H\x1b[s\x1b[H\x1b[u");
+        assert_eq!(cursor(&performer), (3, 4));
+    }
+
+    #[test]
+    fn erase_in_display() {
+        let fill = "aaaa\r\nbbbb\r\ncccc\x1b[2;3H";
+        let mut performer = Performer::default(3, 4, 0);
+
+        feed(&mut performer, fill);
+        feed(&mut performer, "\x1b[J");
+        assert_eq!(screen(&performer.grid), ["aaaa", "bb", ""]);
+
+        feed(&mut performer, "\x1b[H");
+        feed(&mut performer, fill);
+        feed(&mut performer, "\x1b[1J");
+        assert_eq!(screen(&performer.grid), ["", "   b", "cccc"]);
+
+        feed(&mut performer, "\x1b[2J");
+        assert_eq!(screen(&performer.grid), ["", "", ""]);
+        assert_eq!(cursor(&performer), (1, 2));
+    }
+
+    #[test]
+    fn erase_in_line() {
+        let mut performer = Performer::default(1, 6, 0);
+
+        feed(&mut performer, "abcdef\x1b[3G\x1b[K");
+        assert_eq!(screen(&performer.grid), ["ab"]);
+
+        feed(&mut performer, "\rabcdef\x1b[3G\x1b[1K");
+        assert_eq!(screen(&performer.grid), ["   def"]);
+
+        feed(&mut performer, "\x1b[2K");
+        assert_eq!(screen(&performer.grid), [""]);
+    }
+
+    #[test]
+    fn erase_uses_the_current_background() {
+        let mut performer = Performer::default(1, 4, 0);
+
+        feed(&mut performer, "ab\x1b[41m\x1b[K");
+
+        assert_eq!(performer.grid.cell(0, 1).bg, Color::Background);
+        assert_eq!(performer.grid.cell(0, 2).bg, Color::Indexed(1));
+        assert_eq!(performer.grid.cell(0, 3).bg, Color::Indexed(1));
+    }
+
+    #[test]
+    fn insert_delete_and_erase_characters() {
+        let mut performer = Performer::default(1, 6, 0);
+
+        feed(&mut performer, "abcdef\x1b[2G\x1b[2@");
+        assert_eq!(screen(&performer.grid), ["a  bcd"]);
+
+        feed(&mut performer, "\x1b[3P");
+        assert_eq!(screen(&performer.grid), ["acd"]);
+
+        feed(&mut performer, "\rabcdef\x1b[2G\x1b[2X");
+        assert_eq!(screen(&performer.grid), ["a  def"]);
+        assert_eq!(cursor(&performer), (0, 1));
+    }
+
+    #[test]
+    fn insert_and_delete_lines_inside_the_scroll_region() {
+        let mut performer = Performer::default(5, 3, 0);
+
+        feed(&mut performer, "a\r\nb\r\nc\r\nd\r\ne");
+        feed(&mut performer, "\x1b[2;4r\x1b[3H\x1b[L");
+        assert_eq!(screen(&performer.grid), ["a", "b", "", "c", "e"]);
+
+        feed(&mut performer, "\x1b[2H\x1b[2M");
+        assert_eq!(screen(&performer.grid), ["a", "c", "", "", "e"]);
+
+        // Outside the region nothing happens.
+        feed(&mut performer, "\x1b[5H\x1b[L");
+        assert_eq!(screen(&performer.grid), ["a", "c", "", "", "e"]);
+    }
+
+    #[test]
+    fn linefeed_scrolls_only_the_scroll_region() {
+        let mut performer = Performer::default(4, 3, 10);
+
+        feed(&mut performer, "a\r\nb\r\nc\r\nd");
+        feed(&mut performer, "\x1b[2;3r\x1b[3Hx\n\ny");
+
+        assert_eq!(screen(&performer.grid), ["a", "", " y", "d"]);
+        assert!(performer.grid.history.is_empty());
+    }
+
+    #[test]
+    fn scroll_up_and_down() {
+        let mut performer = Performer::default(3, 3, 0);
+
+        feed(&mut performer, "a\r\nb\r\nc\x1b[S");
+        assert_eq!(screen(&performer.grid), ["b", "c", ""]);
+
+        feed(&mut performer, "\x1b[2T");
+        assert_eq!(screen(&performer.grid), ["", "", "b"]);
+    }
+
+    #[test]
+    fn reverse_index_scrolls_down_at_the_top() {
+        let mut performer = Performer::default(3, 3, 0);
+
+        feed(&mut performer, "a\r\nb\r\nc\x1b[H\x1bM");
+
+        assert_eq!(screen(&performer.grid), ["", "a", "b"]);
+        assert_eq!(cursor(&performer), (0, 0));
+    }
+
+    #[test]
+    fn invalid_scroll_region_resets_to_the_screen() {
+        let mut performer = Performer::default(4, 3, 0);
+
+        feed(&mut performer, "\x1b[2;3r");
+        assert_eq!(performer.grid.scroll_region, 1..3);
+
+        feed(&mut performer, "\x1b[3;3r");
+        assert_eq!(performer.grid.scroll_region, 0..4);
+    }
+
+    #[test]
+    fn sgr_attributes() {
+        let mut performer = Performer::default(1, 10, 0);
+
+        feed(&mut performer, "\x1b[1;3;4;9ma\x1b[22;23mb\x1b[0mc");
+
+        let grid = &performer.grid;
+
+        assert_eq!(
+            grid.cell(0, 0).flags,
+            Flags::BOLD | Flags::ITALIC | Flags::UNDERLINE | Flags::STRIKEOUT
+        );
+        assert_eq!(grid.cell(0, 1).flags, Flags::UNDERLINE | Flags::STRIKEOUT);
+        assert_eq!(grid.cell(0, 2).flags, Flags::empty());
+    }
+
+    #[test]
+    fn sgr_colors() {
+        let mut performer = Performer::default(1, 10, 0);
+
+        feed(
+            &mut performer,
+            "\x1b[31;42ma\x1b[91;107mb\x1b[38;5;200;48;2;1;2;3mc\x1b[38:2::4:5:6;48:5:17md\x1b[39;49me",
+        );
+
+        let grid = &performer.grid;
+
+        assert_eq!(grid.cell(0, 0).fg, Color::Indexed(1));
+        assert_eq!(grid.cell(0, 0).bg, Color::Indexed(2));
+        assert_eq!(grid.cell(0, 1).fg, Color::Indexed(9));
+        assert_eq!(grid.cell(0, 1).bg, Color::Indexed(15));
+        assert_eq!(grid.cell(0, 2).fg, Color::Indexed(200));
+        assert_eq!(grid.cell(0, 2).bg, Color::Spec(Rgb::new(1, 2, 3)));
+        assert_eq!(grid.cell(0, 3).fg, Color::Spec(Rgb::new(4, 5, 6)));
+        assert_eq!(grid.cell(0, 3).bg, Color::Indexed(17));
+        assert_eq!(grid.cell(0, 4).fg, Color::Foreground);
+        assert_eq!(grid.cell(0, 4).bg, Color::Background);
+    }
+
+    #[test]
+    fn alternate_screen_1049() {
+        let mut performer = Performer::default(3, 5, 0);
+
+        feed(&mut performer, "main\x1b[2;3H\x1b[?1049h");
+
+        assert!(performer.mode.contains(Mode::ALT_SCREEN));
+        assert_eq!(screen(&performer.grid), ["", "", ""]);
+        assert_eq!(cursor(&performer), (1, 2));
+
+        feed(&mut performer, "alt\x1b[H\x1b[?1049l");
+
+        assert!(!performer.mode.contains(Mode::ALT_SCREEN));
+        assert_eq!(screen(&performer.grid), ["main", "", ""]);
+        assert_eq!(cursor(&performer), (1, 2));
+    }
+
+    #[test]
+    fn alternate_screen_47_and_1047() {
+        let mut performer = Performer::default(2, 5, 0);
+
+        feed(&mut performer, "main\x1b[?47h\x1b[Halt\x1b[?47l");
+        assert_eq!(screen(&performer.grid), ["main", ""]);
+
+        // 47 keeps the alternate screen, so it shows again.
+        feed(&mut performer, "\x1b[?47h");
+        assert_eq!(screen(&performer.grid), ["alt", ""]);
+
+        // 1047 clears it on the way out.
+        feed(&mut performer, "\x1b[?1047l\x1b[?1047h");
+        assert_eq!(screen(&performer.grid), ["", ""]);
+    }
+
+    #[test]
+    fn save_cursor_1048() {
+        let mut performer = Performer::default(3, 5, 0);
+
+        feed(&mut performer, "\x1b[2;2H\x1b[?1048h\x1b[H\x1b[?1048l");
+
+        assert_eq!(cursor(&performer), (1, 1));
+    }
+
+    #[test]
+    fn keyboard_modes() {
+        let mut performer = Performer::default(1, 1, 0);
+
+        feed(&mut performer, "\x1b[?1h\x1b=");
+        assert_eq!(performer.mode, Mode::APP_CURSOR | Mode::APP_KEYPAD);
+
+        feed(&mut performer, "\x1b[?1l\x1b>");
+        assert_eq!(performer.mode, Mode::empty());
+    }
+}
@@ -0,0 +1,296 @@
+use nix::{
+    errno::Errno,
+    libc,
+    pty::{self, Winsize},
+    sys::wait::{self, WaitStatus},
+    unistd::{self, ForkResult, Pid},
+};
+use std::{
+    env,
+    ffi::{CString, OsString},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        io::RawFd,
+    },
+    path::PathBuf,
+    sync::Arc,
+};
+
+pub const BUFFER_SIZE: usize = 65536;
+
+/// The terminal type advertised to programs running in the PTY.
+pub const TERM: &str = "xterm-256color";
+
+nix::ioctl_write_ptr_bad!(set_window_size, libc::TIOCSWINSZ, Winsize);
+
+/// The program run in the PTY and how to run it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShellCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Runs the program as a login shell, by prefixing its `argv[0]` with a dash.
+    pub login: bool,
+    pub working_directory: Option<PathBuf>,
+    /// Variables set in the child's environment, after the ones foxterm sets itself. The
+    /// program is still looked up in foxterm's own `$PATH`.
+    pub env: Vec<(String, String)>,
+}
+
+impl ShellCommand {
+    pub fn new(
+        program: String,
+        args: Vec<String>,
+        login: bool,
+        working_directory: Option<PathBuf>,
+        env: Vec<(String, String)>,
+    ) -> Self {
+        Self {
+            program,
+            args,
+            login,
+            working_directory,
+            env,
+        }
+    }
+
+    /// The argument vector, starting with the name the program is run as.
+    fn argv(&self) -> anyhow::Result<Vec<CString>> {
+        let name = self.program.rsplit('/').next().unwrap_or(&self.program);
+        let arg0 = if self.login {
+            format!("-{}", name)
+        } else {
+            self.program.clone()
+        };
+
+        let mut argv = vec![CString::new(arg0)?];
+
+        for arg in &self.args {
+            argv.push(CString::new(arg.as_str())?);
+        }
+
+        Ok(argv)
+    }
+
+    /// The child's environment: foxterm's own, with the terminal variables and then the
+    /// configured ones set on top.
+    fn envp(&self) -> anyhow::Result<Vec<CString>> {
+        let mut vars: Vec<(OsString, OsString)> = env::vars_os().collect();
+        let terminal_vars = [
+            ("TERM", TERM),
+            ("COLORTERM", "truecolor"),
+            ("TERM_PROGRAM", "foxterm"),
+        ];
+        let configured_vars = self.env.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+
+        for (key, value) in terminal_vars.into_iter().chain(configured_vars) {
+            vars.retain(|(k, _)| k != key);
+            vars.push((key.into(), value.into()));
+        }
+
+        let mut envp = Vec::with_capacity(vars.len());
+
+        for (key, value) in vars {
+            let mut var = key.into_vec();
+
+            var.push(b'=');
+            var.extend(value.into_vec());
+            envp.push(CString::new(var)?);
+        }
+
+        Ok(envp)
+    }
+}
+
+pub struct Pty {
+    pub fd: RawFd,
+    pub child: Pid,
+}
+
+impl Pty {
+    pub fn new(fd: RawFd, child: Pid) -> Self {
+        Self { fd, child }
+    }
+
+    /// Forks and runs `command` in the child, attached to a new PTY. Only the parent returns,
+    /// the child exits when the command cannot be executed.
+    ///
+    /// Everything the child needs is prepared before the fork, since other threads may hold
+    /// locks, such as the one on the environment, that would never be released in the child.
+    pub fn spawn(command: &ShellCommand, winsize: &Winsize) -> anyhow::Result<Arc<Self>> {
+        let program = CString::new(command.program.as_str())?;
+        let argv = command.argv()?;
+        let envp = command.envp()?;
+        let dir = match &command.working_directory {
+            Some(dir) => Some((
+                CString::new(dir.as_os_str().as_bytes())?,
+                format!("Failed to change directory to {}: ", dir.display()),
+            )),
+            None => None,
+        };
+        let exec_failed = format!("Failed to execute {}: ", command.program);
+        let fork_pty = unsafe { pty::forkpty(Some(winsize), None)? };
+
+        match fork_pty.fork_result {
+            ForkResult::Parent { child } => Ok(Arc::new(Self::new(fork_pty.master, child))),
+            ForkResult::Child => {
+                if let Some((dir, chdir_failed)) = &dir {
+                    if let Err(e) = unistd::chdir(dir.as_c_str()) {
+                        Self::report_error(chdir_failed, e);
+                    }
+                }
+
+                let e = unistd::execvpe(&program, &argv, &envp).unwrap_err();
+
+                Self::report_error(&exec_failed, e);
+
+                // Unlike `process::exit`, this does not run the parent's exit handlers or flush
+                // the buffers the child inherited from it.
+                unsafe { libc::_exit(1) }
+            }
+        }
+    }
+
+    /// Writes `message` followed by the description of `e` to stderr without allocating or
+    /// taking locks, as the child may only make async-signal-safe calls before the exec.
+    fn report_error(message: &str, e: Errno) {
+        for part in [message, e.desc(), "\n"] {
+            let _ = unistd::write(libc::STDERR_FILENO, part.as_bytes());
+        }
+    }
+
+    /// Blocks until the child exits and reaps it, returning its exit code. A child killed by a
+    /// signal reports 128 plus the signal number, like shells do.
+    pub fn wait(&self) -> anyhow::Result<i32> {
+        loop {
+            match wait::waitpid(self.child, None)? {
+                WaitStatus::Exited(_, code) => return Ok(code),
+                WaitStatus::Signaled(_, signal, _) => return Ok(128 + signal as i32),
+                _ => {}
+            }
+        }
+    }
+
+    /// Tells the child the new terminal size, which also delivers `SIGWINCH` to it.
+    pub fn resize(&self, winsize: &Winsize) -> anyhow::Result<()> {
+        unsafe { set_window_size(self.fd, winsize)? };
+
+        Ok(())
+    }
+
+    /// Reads up to [`BUFFER_SIZE`] bytes of output. An empty buffer means end of file.
+    pub fn read(&self) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let len = unistd::read(self.fd, &mut buffer)?;
+
+        buffer.truncate(len);
+
+        Ok(buffer)
+    }
+
+    pub fn write(&self, buffer: &[u8]) -> anyhow::Result<()> {
+        unistd::write(self.fd, buffer)?;
+
+        Ok(())
+    }
+
+    pub fn close(&self) -> anyhow::Result<()> {
+        unistd::close(self.fd)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `command` in a PTY, returning everything it printed and its exit code.
+    fn run(command: &ShellCommand) -> (String, i32) {
+        let winsize = Winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = Pty::spawn(command, &winsize).unwrap();
+        let mut output = Vec::new();
+
+        // Linux reports EIO once the child exited and its side of the PTY is closed.
+        while let Ok(buffer) = pty.read() {
+            if buffer.is_empty() {
+                break;
+            }
+
+            output.extend(buffer);
+        }
+
+        (String::from_utf8(output).unwrap(), pty.wait().unwrap())
+    }
+
+    #[test]
+    fn reports_programs_that_cannot_be_executed() {
+        let command = ShellCommand::new(
+            "/nonexistent".to_owned(),
+            Vec::new(),
+            false,
+            None,
+            Vec::new(),
+        );
+
+        assert_eq!(
+            run(&command),
+            (
+                "Failed to execute /nonexistent: No such file or directory\r\n".to_owned(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn sets_the_environment_and_working_directory() {
+        let command = ShellCommand::new(
+            "/bin/sh".to_owned(),
+            vec![
+                "-c".to_owned(),
+                "echo \"$TERM $COLORTERM $FOXTERM_TEST\"; pwd".to_owned(),
+            ],
+            false,
+            Some(PathBuf::from("/")),
+            vec![
+                ("FOXTERM_TEST".to_owned(), "set".to_owned()),
+                ("COLORTERM".to_owned(), "24bit".to_owned()),
+            ],
+        );
+
+        assert_eq!(
+            run(&command),
+            ("xterm-256color 24bit set\r\n/\r\n".to_owned(), 0)
+        );
+    }
+
+    #[test]
+    fn runs_in_the_current_directory_when_the_configured_one_is_missing() {
+        let command = ShellCommand::new(
+            "/bin/true".to_owned(),
+            Vec::new(),
+            false,
+            Some(PathBuf::from("/nonexistent")),
+            Vec::new(),
+        );
+
+        assert_eq!(
+            run(&command),
+            (
+                "Failed to change directory to /nonexistent: No such file or directory\r\n"
+                    .to_owned(),
+                0
+            )
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::terminal::config::{Config, Override};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// A GPU accelerated terminal emulator.
+#[derive(Debug, Clone, Parser)]
+#[clap(version, about)]
+pub struct Options {
+    /// Run this command and its arguments instead of the shell
+    #[clap(
+        short = 'e',
+        long = "command",
+        multiple_values = true,
+        allow_hyphen_values = true
+    )]
+    pub command: Vec<String>,
+
+    /// Load the config from this file instead of the default location
+    #[clap(long)]
+    pub config: Option<String>,
+
+    /// Start the shell in this directory
+    #[clap(long)]
+    pub working_directory: Option<String>,
+
+    /// Set the window title
+    #[clap(long)]
+    pub title: Option<String>,
+
+    /// Set the window class, used as the app id on Wayland
+    #[clap(long)]
+    pub class: Option<String>,
+
+    /// Override a config value, for example `-o font.scale=28`
+    #[clap(short = 'o', long = "option", value_name = "KEY=VALUE")]
+    pub overrides: Vec<Override>,
+}
+
+impl Options {
+    /// The config file chosen on the command line, or else the first one found in the XDG
+    /// config directories, or else the user's config file.
+    pub fn config_path(&self) -> PathBuf {
+        match &self.config {
+            Some(path) => PathBuf::from(path),
+            None => Config::find_path().unwrap_or_else(Config::user_path),
+        }
+    }
+
+    /// Loads the config file chosen on the command line, with the options applied on top. When
+    /// no config file exists anywhere, the default config is written to the user's config file
+    /// first.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        let path = self.config_path();
+
+        if self.config.is_none() && !path.exists() {
+            Config::default().create_file(&path)?;
+        }
+
+        let mut config = Config::from_file(&path, &self.overrides)?;
+
+        self.apply(&mut config);
+
+        Ok(config)
+    }
+
+    /// Like [`Options::load_config`], but falls back to the default config with a warning when
+    /// the file cannot be loaded.
+    pub fn load_config_or_default(&self) -> Config {
+        self.load_config().unwrap_or_else(|e| {
+            eprintln!("Warning: {:#}, using the default config", e);
+
+            let mut config = Config::default();
+
+            self.apply(&mut config);
+
+            config
+        })
+    }
+
+    /// Applies the options that replace config values.
+    fn apply(&self, config: &mut Config) {
+        if let Some((program, args)) = self.command.split_first() {
+            config.shell.program = Some(program.clone());
+            config.shell.args = args.to_vec();
+            config.shell.login = false;
+        }
+
+        if let Some(dir) = &self.working_directory {
+            config.shell.working_directory = Some(dir.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_yaml::Value;
+
+    #[test]
+    fn parses_overrides_and_the_command() {
+        let options = Options::try_parse_from([
+            "foxterm",
+            "-o",
+            "font.scale=28",
+            "--option",
+            "close_on_exit=false",
+            "-e",
+            "htop",
+            "-d",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            options.overrides,
+            [
+                Override::new(vec!["font".into(), "scale".into()], Value::from(28)),
+                Override::new(vec!["close_on_exit".into()], Value::from(false)),
+            ]
+        );
+        assert_eq!(options.command, ["htop", "-d"]);
+    }
+
+    #[test]
+    fn the_command_replaces_the_shell() {
+        let options = Options::try_parse_from(["foxterm", "-e", "htop", "-d"]).unwrap();
+        let mut config = Config::default();
+
+        config.shell.login = true;
+        options.apply(&mut config);
+
+        assert_eq!(config.shell.program.as_deref(), Some("htop"));
+        assert_eq!(config.shell.args, ["-d"]);
+        assert!(!config.shell.login);
+    }
+}
@@ -0,0 +1,45 @@
+use bytemuck::{Pod, Zeroable};
+
+/// Per cell data for the instanced text pipeline. Every visible cell becomes one instance of a
+/// unit quad, which the shaders place on the grid and fill with the background, the glyph and
+/// any lines.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Zeroable, Pod)]
+pub struct CellInstance {
+    /// Column and row of the cell.
+    pub cell: [f32; 2],
+    /// Offset and dimensions of the glyph within the cell, in pixels.
+    pub glyph: [f32; 4],
+    /// The glyph's rectangle in the atlas, in texels.
+    pub uv_rect: [f32; 4],
+    pub fg: [f32; 4],
+    pub bg: [f32; 4],
+    pub flags: u32,
+}
+
+vulkano::impl_vertex!(CellInstance, cell, glyph, uv_rect, fg, bg, flags);
+
+impl CellInstance {
+    /// The cell spans two columns.
+    pub const WIDE_CHAR: u32 = 1;
+    pub const UNDERLINE: u32 = 1 << 1;
+    pub const STRIKEOUT: u32 = 1 << 2;
+
+    pub fn new(
+        cell: [f32; 2],
+        glyph: [f32; 4],
+        uv_rect: [f32; 4],
+        fg: [f32; 4],
+        bg: [f32; 4],
+        flags: u32,
+    ) -> Self {
+        Self {
+            cell,
+            glyph,
+            uv_rect,
+            fg,
+            bg,
+            flags,
+        }
+    }
+}
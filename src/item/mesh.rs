@@ -1,5 +1,5 @@
 use bytemuck::{Pod, Zeroable};
-use cgmath::Vector2;
+use cgmath::{Vector2, Zero};
 use std::sync::Arc;
 use vulkano::{
     buffer::{BufferUsage, ImmutableBuffer},
@@ -15,6 +15,9 @@ pub struct Vertex {
 
 vulkano::impl_vertex!(Vertex, position, uv);
 
+/// The position and dimensions of a quad.
+pub type Rect = (Vector2<f32>, Vector2<f32>);
+
 pub struct Mesh {
     pub vertices: Arc<ImmutableBuffer<[Vertex]>>,
     pub indices: Arc<ImmutableBuffer<[u32]>>,
@@ -48,29 +51,40 @@ impl Mesh {
     }
 
     pub fn from_rect(queue: Arc<Queue>, dimensions: Vector2<f32>) -> anyhow::Result<Self> {
+        Self::from_rects(queue, &[(Vector2::zero(), dimensions)])
+    }
+
+    /// Creates a mesh of textured quads, one for each `(position, dimensions)` pair.
+    pub fn from_rects(queue: Arc<Queue>, rects: &[Rect]) -> anyhow::Result<Self> {
         const INDICES: &[u32] = &[0, 1, 2, 1, 2, 3];
 
-        let vertices = {
-            [
-                Vertex {
-                    uv: [0.0, 0.0],
-                    position: [0.0, 0.0, 0.0],
-                },
-                Vertex {
-                    uv: [0.0, 1.0],
-                    position: [0.0, dimensions.y, 0.0],
-                },
-                Vertex {
-                    uv: [1.0, 0.0],
-                    position: [dimensions.x, 0.0, 0.0],
-                },
-                Vertex {
-                    uv: [1.0, 1.0],
-                    position: [dimensions.x, dimensions.y, 0.0],
-                },
-            ]
-        };
+        let vertices = rects
+            .iter()
+            .flat_map(|(pos, dimensions)| {
+                [
+                    Vertex {
+                        uv: [0.0, 0.0],
+                        position: [pos.x, pos.y, 0.0],
+                    },
+                    Vertex {
+                        uv: [0.0, 1.0],
+                        position: [pos.x, pos.y + dimensions.y, 0.0],
+                    },
+                    Vertex {
+                        uv: [1.0, 0.0],
+                        position: [pos.x + dimensions.x, pos.y, 0.0],
+                    },
+                    Vertex {
+                        uv: [1.0, 1.0],
+                        position: [pos.x + dimensions.x, pos.y + dimensions.y, 0.0],
+                    },
+                ]
+            })
+            .collect::<Vec<_>>();
+        let indices = (0..rects.len() as u32)
+            .flat_map(|i| INDICES.iter().map(move |index| index + i * 4))
+            .collect::<Vec<_>>();
 
-        Self::from_data(queue, &vertices, INDICES)
+        Self::from_data(queue, &vertices, &indices)
     }
 }
@@ -1,30 +1,37 @@
 use std::sync::Arc;
 use vulkano::{
-    device::{Device, Queue},
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{
+        pool::standard::StandardCommandPoolBuilder, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    device::{DeviceOwned, Queue},
     format::Format,
-    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount},
+    image::{
+        view::ImageView, ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage,
+    },
     sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
 };
 
+#[derive(Clone)]
 pub struct Texture {
-    pub image: Arc<ImageView<ImmutableImage>>,
+    pub image: Arc<ImageView<StorageImage>>,
     pub sampler: Arc<Sampler>,
 }
 
 impl Texture {
-    pub fn new(image: Arc<ImageView<ImmutableImage>>, sampler: Arc<Sampler>) -> Self {
+    pub fn new(image: Arc<ImageView<StorageImage>>, sampler: Arc<Sampler>) -> Self {
         Self { image, sampler }
     }
 
-    pub fn from_data(
-        device: Arc<Device>,
+    /// A texture whose contents are undefined until they are written with [`Texture::write`].
+    pub fn empty(
         queue: Arc<Queue>,
         format: Format,
         dimensions: ImageDimensions,
-        data: &[u8],
     ) -> anyhow::Result<Self> {
         let sampler = Sampler::new(
-            device,
+            queue.device().clone(),
             SamplerCreateInfo {
                 mag_filter: Filter::Nearest,
                 min_filter: Filter::Nearest,
@@ -34,29 +41,56 @@ impl Texture {
                 ..Default::default()
             },
         )?;
-        let (image, _) = ImmutableImage::from_iter(
-            data.iter().cloned(),
+        let image = StorageImage::with_usage(
+            queue.device().clone(),
             dimensions,
-            MipmapsCount::One,
             format,
-            queue,
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            ImageCreateFlags::none(),
+            [queue.family()],
         )?;
         let image = ImageView::new_default(image)?;
 
         Ok(Self::new(image, sampler))
     }
 
-    pub fn white(device: Arc<Device>, queue: Arc<Queue>) -> anyhow::Result<Self> {
-        Self::from_data(
-            device,
-            queue,
-            Format::R8_UNORM,
-            ImageDimensions::Dim2d {
-                width: 1,
-                height: 1,
-                array_layers: 1,
-            },
-            &[u8::MAX],
-        )
+    pub fn dimensions(&self) -> ImageDimensions {
+        self.image.image().dimensions()
+    }
+
+    /// Records copying `data`, a `size[0]` by `size[1]` block of texels, to `offset` in the
+    /// texture. The copy has to be recorded outside of any render pass.
+    pub fn write(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        offset: [u32; 2],
+        size: [u32; 2],
+        data: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let buffer = CpuAccessibleBuffer::from_iter(
+            builder.device().clone(),
+            BufferUsage::transfer_source(),
+            false,
+            data,
+        )?;
+
+        builder.copy_buffer_to_image_dimensions(
+            buffer,
+            self.image.image().clone(),
+            [offset[0], offset[1], 0],
+            [size[0], size[1], 1],
+            0,
+            1,
+            0,
+        )?;
+
+        Ok(())
     }
 }
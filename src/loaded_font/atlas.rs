@@ -0,0 +1,187 @@
+use cgmath::Vector4;
+
+pub const INITIAL_ATLAS_SIZE: u32 = 512;
+pub const MAX_ATLAS_SIZE: u32 = 8192;
+/// Empty texels left between glyphs so that sampling one never bleeds into its neighbours.
+pub const GLYPH_PADDING: u32 = 1;
+
+/// A row of glyphs in the atlas, all no taller than the row itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shelf {
+    pub y: u32,
+    pub height: u32,
+    pub x: u32,
+}
+
+impl Shelf {
+    pub fn new(y: u32, height: u32, x: u32) -> Self {
+        Self { y, height, x }
+    }
+}
+
+/// A single channel texture holding every rasterized glyph, packed into shelves. The atlas
+/// doubles in size when it runs out of space, keeping existing glyphs where they are.
+pub struct Atlas {
+    pub size: u32,
+    pub data: Vec<u8>,
+    pub shelves: Vec<Shelf>,
+    /// The texels changed since the atlas was last uploaded, as `(x, y, width, height)`.
+    pub dirty: Option<Vector4<u32>>,
+}
+
+impl Atlas {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            data: vec![0; (size * size) as usize],
+            shelves: Vec::new(),
+            dirty: Some(Vector4::new(0, 0, size, size)),
+        }
+    }
+
+    /// Copies a `width` by `height` bitmap into the atlas. Returns the rectangle it occupies
+    /// as `(x, y, width, height)` in texels, or `None` when the atlas cannot grow any further.
+    pub fn insert(&mut self, width: u32, height: u32, bitmap: &[u8]) -> Option<Vector4<f32>> {
+        let (x, y) = loop {
+            match self.allocate(width, height) {
+                Some(pos) => break pos,
+                None if self.size < MAX_ATLAS_SIZE => self.grow(),
+                None => return None,
+            }
+        };
+
+        for (row, line) in bitmap.chunks_exact(width as usize).enumerate() {
+            let start = ((y + row as u32) * self.size + x) as usize;
+
+            self.data[start..start + width as usize].copy_from_slice(line);
+        }
+
+        self.mark_dirty(x, y, width, height);
+
+        Some(Vector4::new(
+            x as f32,
+            y as f32,
+            width as f32,
+            height as f32,
+        ))
+    }
+
+    /// Finds space on the shortest shelf the glyph fits on, opening a new shelf if none does.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let padded_width = width + GLYPH_PADDING;
+        let padded_height = height + GLYPH_PADDING;
+        let size = self.size;
+
+        if let Some(shelf) = self
+            .shelves
+            .iter_mut()
+            .filter(|shelf| shelf.height >= padded_height && shelf.x + padded_width <= size)
+            .min_by_key(|shelf| shelf.height)
+        {
+            let pos = (shelf.x, shelf.y);
+
+            shelf.x += padded_width;
+
+            return Some(pos);
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+
+        if y + padded_height > size || padded_width > size {
+            return None;
+        }
+
+        self.shelves
+            .push(Shelf::new(y, padded_height, padded_width));
+
+        Some((0, y))
+    }
+
+    fn grow(&mut self) {
+        let size = self.size * 2;
+        let mut data = vec![0; (size * size) as usize];
+
+        for (row, line) in self.data.chunks_exact(self.size as usize).enumerate() {
+            let start = row * size as usize;
+
+            data[start..start + line.len()].copy_from_slice(line);
+        }
+
+        self.size = size;
+        self.data = data;
+        self.mark_dirty(0, 0, size, size);
+    }
+
+    /// Grows the dirty rectangle to include the given one.
+    pub fn mark_dirty(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => {
+                let left = dirty.x.min(x);
+                let top = dirty.y.min(y);
+                let right = (dirty.x + dirty.z).max(x + width);
+                let bottom = (dirty.y + dirty.w).max(y + height);
+
+                Vector4::new(left, top, right - left, bottom - top)
+            }
+            None => Vector4::new(x, y, width, height),
+        });
+    }
+
+    /// The dirty rectangle together with its texels, row by row, clearing it so that the next
+    /// call only returns what changed after this one.
+    pub fn take_dirty(&mut self) -> Option<(Vector4<u32>, Vec<u8>)> {
+        let dirty = self.dirty.take()?;
+        let mut texels = Vec::with_capacity((dirty.z * dirty.w) as usize);
+
+        for row in dirty.y..dirty.y + dirty.w {
+            let start = (row * self.size + dirty.x) as usize;
+
+            texels.extend_from_slice(&self.data[start..start + dirty.z as usize]);
+        }
+
+        Some((dirty, texels))
+    }
+}
+
+impl Default for Atlas {
+    fn default() -> Self {
+        Self::new(INITIAL_ATLAS_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_new_glyphs_are_dirty() {
+        let mut atlas = Atlas::new(8);
+
+        assert_eq!(atlas.take_dirty().unwrap().0, Vector4::new(0, 0, 8, 8));
+        assert_eq!(atlas.take_dirty(), None);
+
+        atlas.insert(2, 2, &[1, 2, 3, 4]);
+        atlas.insert(1, 1, &[5]);
+
+        let (dirty, texels) = atlas.take_dirty().unwrap();
+
+        assert_eq!(dirty, Vector4::new(0, 0, 4, 2));
+        assert_eq!(texels, [1, 2, 0, 5, 3, 4, 0, 0]);
+        assert_eq!(atlas.take_dirty(), None);
+    }
+
+    #[test]
+    fn growing_dirties_the_whole_atlas() {
+        let mut atlas = Atlas::new(4);
+
+        atlas.take_dirty();
+        atlas.insert(4, 4, &[1; 16]);
+
+        assert_eq!(atlas.size, 8);
+        assert_eq!(atlas.take_dirty().unwrap().0, Vector4::new(0, 0, 8, 8));
+    }
+}
@@ -0,0 +1,156 @@
+use super::chr::Chr;
+use fontdue::{Font, Metrics};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// The horizontal shift per pixel of height used to slant glyphs, about 12 degrees.
+pub const SYNTHETIC_SLANT: f32 = 0.2;
+/// The font scale per extra pixel of stroke width used to embolden glyphs.
+pub const SYNTHETIC_BOLD_DIVISOR: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontStyle {
+    pub const ALL: [Self; 4] = [Self::Regular, Self::Bold, Self::Italic, Self::BoldItalic];
+
+    pub fn new(bold: bool, italic: bool) -> Self {
+        match (bold, italic) {
+            (false, false) => Self::Regular,
+            (true, false) => Self::Bold,
+            (false, true) => Self::Italic,
+            (true, true) => Self::BoldItalic,
+        }
+    }
+
+    pub fn is_bold(self) -> bool {
+        matches!(self, Self::Bold | Self::BoldItalic)
+    }
+
+    pub fn is_italic(self) -> bool {
+        matches!(self, Self::Italic | Self::BoldItalic)
+    }
+}
+
+/// A font used for one style, with its own glyph cache. When the style has no font of its own
+/// another one is used, emboldened or slanted to make up the difference.
+pub struct Face {
+    pub font: Arc<Font>,
+    pub embolden: bool,
+    pub slant: bool,
+    pub chrs: RwLock<HashMap<char, Option<Arc<Chr>>>>,
+}
+
+impl Face {
+    pub fn new(
+        font: Arc<Font>,
+        embolden: bool,
+        slant: bool,
+        chrs: RwLock<HashMap<char, Option<Arc<Chr>>>>,
+    ) -> Self {
+        Self {
+            font,
+            embolden,
+            slant,
+            chrs,
+        }
+    }
+
+    /// Rasterizes `c` at `scale`, applying the synthetic styles. Returns `None` when the font
+    /// does not cover `c`.
+    pub fn rasterize(&self, c: char, scale: f32) -> Option<(Metrics, Vec<u8>)> {
+        Self::rasterize_font(&self.font, c, scale, self.embolden, self.slant)
+    }
+
+    /// Rasterizes `c` from any font, emboldening or slanting it as asked.
+    pub fn rasterize_font(
+        font: &Font,
+        c: char,
+        scale: f32,
+        embolden: bool,
+        slant: bool,
+    ) -> Option<(Metrics, Vec<u8>)> {
+        if font.lookup_glyph_index(c) == 0 {
+            return None;
+        }
+
+        let (mut metrics, mut bitmap) = font.rasterize(c, scale);
+
+        if metrics.width == 0 || metrics.height == 0 {
+            return Some((metrics, bitmap));
+        }
+
+        if embolden {
+            let strength = (scale / SYNTHETIC_BOLD_DIVISOR).round().max(1.0) as usize;
+
+            bitmap = Self::embolden(&mut metrics, &bitmap, strength);
+        }
+
+        if slant {
+            bitmap = Self::slant(&mut metrics, &bitmap);
+        }
+
+        Some((metrics, bitmap))
+    }
+
+    /// Widens every stroke by `strength` pixels to the right.
+    fn embolden(metrics: &mut Metrics, bitmap: &[u8], strength: usize) -> Vec<u8> {
+        let width = metrics.width + strength;
+        let mut emboldened = vec![0; width * metrics.height];
+
+        for (src, dst) in bitmap
+            .chunks_exact(metrics.width)
+            .zip(emboldened.chunks_exact_mut(width))
+        {
+            for (x, &coverage) in src.iter().enumerate() {
+                for texel in &mut dst[x..=x + strength] {
+                    *texel = (*texel).max(coverage);
+                }
+            }
+        }
+
+        metrics.width = width;
+
+        emboldened
+    }
+
+    /// Shears the glyph to the right in proportion to the height above the baseline, blending
+    /// each texel across the two it lands between.
+    fn slant(metrics: &mut Metrics, bitmap: &[u8]) -> Vec<u8> {
+        // The height of the top of each row above the baseline, from the top row down.
+        let row_height = |row: usize| (metrics.ymin + metrics.height as i32) as f32 - row as f32;
+        let min_shift = (row_height(metrics.height - 1) * SYNTHETIC_SLANT).floor();
+        let max_shift = row_height(0) * SYNTHETIC_SLANT;
+        let width = metrics.width + (max_shift - min_shift).ceil() as usize + 1;
+        let mut slanted = vec![0; width * metrics.height];
+
+        for (row, (src, dst)) in bitmap
+            .chunks_exact(metrics.width)
+            .zip(slanted.chunks_exact_mut(width))
+            .enumerate()
+        {
+            let shift = row_height(row) * SYNTHETIC_SLANT - min_shift;
+            let offset = shift.floor() as usize;
+            let fraction = shift.fract();
+
+            for (x, &coverage) in src.iter().enumerate() {
+                let coverage = coverage as f32;
+
+                dst[x + offset] = (dst[x + offset] as f32 + coverage * (1.0 - fraction)) as u8;
+                dst[x + offset + 1] = (dst[x + offset + 1] as f32 + coverage * fraction) as u8;
+            }
+        }
+
+        metrics.xmin += min_shift as i32;
+        metrics.width = width;
+
+        slanted
+    }
+}
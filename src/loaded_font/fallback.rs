@@ -0,0 +1,347 @@
+use crossbeam::channel::{self, Receiver, TryRecvError};
+use fontdue::{Font, FontSettings};
+use std::{
+    cmp::Ordering,
+    env,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+};
+
+/// The extensions of the files picked up when searching the system for fonts.
+pub const FONT_EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+/// The size of the header of a font file, which is followed by the table records.
+const FONT_HEADER_SIZE: usize = 12;
+/// The size of each record in the table directory of a font file.
+const TABLE_RECORD_SIZE: usize = 16;
+
+/// Searched for fonts when `$XDG_DATA_DIRS` is not set.
+pub const DEFAULT_DATA_DIRS: [&str; 2] = ["/usr/local/share", "/usr/share"];
+
+/// A font glyphs are taken from when the configured faces do not cover them, scaled so that its
+/// lines are as tall as those of the primary font. Glyphs too wide for their cells are shrunk
+/// further by [`FallbackFont::scale_for`].
+#[derive(Clone)]
+pub struct FallbackFont {
+    pub font: Arc<Font>,
+    pub scale: f32,
+}
+
+impl FallbackFont {
+    pub fn new(font: Arc<Font>, scale: f32) -> Self {
+        Self { font, scale }
+    }
+
+    /// Scales `font` to match a primary font whose lines are `line_height` pixels tall at
+    /// `scale`.
+    pub fn sized(font: Arc<Font>, scale: f32, line_height: f32) -> Self {
+        let scale = match font.horizontal_line_metrics(scale) {
+            Some(metrics) if metrics.ascent - metrics.descent > 0.0 => {
+                scale * line_height / (metrics.ascent - metrics.descent)
+            }
+            _ => scale,
+        };
+
+        Self::new(font, scale)
+    }
+
+    /// The scale to rasterize `c` at so that it advances no further than `width` pixels,
+    /// shrinking glyphs that would be clipped by their cells.
+    pub fn scale_for(&self, c: char, width: f32) -> f32 {
+        let advance = self.font.metrics(c, self.scale).advance_width;
+
+        if advance > width {
+            self.scale * width / advance
+        } else {
+            self.scale
+        }
+    }
+
+    /// The height of a line of `font` at `scale`, from the lowest descender to the highest
+    /// ascender.
+    pub fn line_height(font: &Font, scale: f32) -> f32 {
+        font.horizontal_line_metrics(scale)
+            .map(|metrics| metrics.ascent - metrics.descent)
+            .unwrap_or(scale)
+    }
+}
+
+/// A font found on the system, which is only loaded once a character it covers is needed.
+pub struct SystemFont {
+    pub path: PathBuf,
+    /// The ranges of code points the font has glyphs for, sorted and not overlapping.
+    pub coverage: Vec<(u32, u32)>,
+    pub font: RwLock<Option<FallbackFont>>,
+}
+
+impl SystemFont {
+    pub fn new(
+        path: PathBuf,
+        coverage: Vec<(u32, u32)>,
+        font: RwLock<Option<FallbackFont>>,
+    ) -> Self {
+        Self {
+            path,
+            coverage,
+            font,
+        }
+    }
+
+    /// Every font in the directories fontconfig searches by default, in the order of their
+    /// paths. Only the table directory and the character map of each file are read, but
+    /// every font on the system is opened, so this is meant to run on a background thread.
+    pub fn discover() -> Vec<Self> {
+        let mut paths = Vec::new();
+
+        for dir in Self::font_dirs() {
+            Self::find_fonts(&dir, &mut paths);
+        }
+
+        paths.sort();
+        paths.dedup();
+
+        paths.into_iter().filter_map(Self::from_path).collect()
+    }
+
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let data = Self::read_cmap(&path)?;
+        let cmap = ttf_parser::cmap::Table::parse(&data)?;
+        let mut codepoints = Vec::new();
+
+        for subtable in cmap.subtables {
+            if subtable.is_unicode() {
+                subtable.codepoints(|c| codepoints.push(c));
+            }
+        }
+
+        codepoints.sort_unstable();
+        codepoints.dedup();
+
+        let mut coverage: Vec<(u32, u32)> = Vec::new();
+
+        for c in codepoints {
+            match coverage.last_mut() {
+                Some((_, end)) if *end + 1 == c => *end = c,
+                _ => coverage.push((c, c)),
+            }
+        }
+
+        Some(Self::new(path, coverage, RwLock::new(None)))
+    }
+
+    /// Reads the `cmap` table of the font at `path`, or of the first font in a collection,
+    /// without reading the rest of the file.
+    fn read_cmap(path: &Path) -> Option<Vec<u8>> {
+        let mut file = File::open(path).ok()?;
+        let len = file.metadata().ok()?.len();
+        let mut header = [0; FONT_HEADER_SIZE];
+
+        file.read_exact(&mut header).ok()?;
+
+        // Collections start with the offsets of the fonts in them.
+        if &header[..4] == b"ttcf" {
+            let mut offset = [0; 4];
+
+            file.read_exact(&mut offset).ok()?;
+            file.seek(SeekFrom::Start(u32::from_be_bytes(offset).into()))
+                .ok()?;
+            file.read_exact(&mut header).ok()?;
+        }
+
+        let num_tables = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let mut records = vec![0; num_tables * TABLE_RECORD_SIZE];
+
+        file.read_exact(&mut records).ok()?;
+
+        let record = records
+            .chunks_exact(TABLE_RECORD_SIZE)
+            .find(|record| &record[..4] == b"cmap")?;
+        let offset = u32::from_be_bytes(record[8..12].try_into().unwrap()) as u64;
+        let size = u32::from_be_bytes(record[12..16].try_into().unwrap()) as u64;
+
+        if offset + size > len {
+            return None;
+        }
+
+        let mut cmap = vec![0; size as usize];
+
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut cmap).ok()?;
+
+        Some(cmap)
+    }
+
+    pub fn covers(&self, c: char) -> bool {
+        let c = c as u32;
+
+        self.coverage
+            .binary_search_by(|&(start, end)| {
+                if end < c {
+                    Ordering::Less
+                } else if start > c {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    /// Loads the font the first time it is used. Returns `None` when it cannot be loaded.
+    pub fn load(&self, scale: f32, line_height: f32) -> Option<FallbackFont> {
+        if let Some(font) = &*self.font.read().unwrap() {
+            return Some(font.clone());
+        }
+
+        let data = fs::read(&self.path).ok()?;
+        let font = Font::from_bytes(data, FontSettings::default()).ok()?;
+        let font = FallbackFont::sized(Arc::new(font), scale, line_height);
+
+        *self.font.write().unwrap() = Some(font.clone());
+
+        Some(font)
+    }
+
+    /// `$XDG_DATA_HOME/fonts`, `~/.fonts` and the `fonts` directory in each of
+    /// `$XDG_DATA_DIRS`.
+    fn font_dirs() -> Vec<PathBuf> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.local/share").as_ref()));
+        let data_dirs = env::var_os("XDG_DATA_DIRS")
+            .map(|dirs| {
+                env::split_paths(&dirs)
+                    .filter(|dir| dir.is_absolute())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| DEFAULT_DATA_DIRS.iter().map(PathBuf::from).collect());
+        let mut dirs = vec![
+            data_home.join("fonts"),
+            PathBuf::from(shellexpand::tilde("~/.fonts").as_ref()),
+        ];
+
+        dirs.extend(data_dirs.into_iter().map(|dir| dir.join("fonts")));
+
+        dirs
+    }
+
+    /// Adds the font files under `dir` to `paths`. Links to directories are not followed, so
+    /// that a link cannot lead back to where it is.
+    fn find_fonts(dir: &Path, paths: &mut Vec<PathBuf>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                Self::find_fonts(&path, paths);
+            } else if path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    FONT_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                })
+            {
+                paths.push(path);
+            }
+        }
+    }
+}
+
+/// The index of the fonts on the system, built on a background thread.
+pub enum SystemFonts {
+    Disabled,
+    /// Receives the index once it is built.
+    Indexing(Receiver<Vec<SystemFont>>),
+    Ready(Arc<Vec<SystemFont>>),
+}
+
+impl SystemFonts {
+    /// Starts indexing the fonts on the system when `discover` is set, calling `on_ready` once
+    /// the index can be used. Until then characters are not looked up in them, since that would
+    /// hold up drawing for as long as the indexing takes.
+    pub fn new(discover: bool, on_ready: impl FnOnce() + Send + 'static) -> Self {
+        if !discover {
+            return Self::Disabled;
+        }
+
+        let (sender, receiver) = channel::bounded(1);
+
+        thread::spawn(move || {
+            let _ = sender.send(SystemFont::discover());
+
+            on_ready();
+        });
+
+        Self::Indexing(receiver)
+    }
+
+    /// The fonts found, or `None` while they are still being indexed.
+    fn get(&mut self) -> Option<Arc<Vec<SystemFont>>> {
+        if let Self::Indexing(receiver) = self {
+            match receiver.try_recv() {
+                Ok(fonts) => *self = Self::Ready(Arc::new(fonts)),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => *self = Self::Ready(Arc::new(Vec::new())),
+            }
+        }
+
+        match self {
+            Self::Ready(fonts) => Some(fonts.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The fonts searched in order for characters the configured faces do not cover: first the
+/// configured fallback fonts, then the fonts on the system when discovery is enabled.
+pub struct Fallback {
+    pub fonts: Vec<FallbackFont>,
+    pub system: Mutex<SystemFonts>,
+    /// The scale and line height of the primary font, which fallback fonts are sized to.
+    pub scale: f32,
+    pub line_height: f32,
+}
+
+impl Fallback {
+    pub fn new(
+        fonts: Vec<FallbackFont>,
+        system: Mutex<SystemFonts>,
+        scale: f32,
+        line_height: f32,
+    ) -> Self {
+        Self {
+            fonts,
+            system,
+            scale,
+            line_height,
+        }
+    }
+
+    /// The first font that has a glyph for `c`. The fonts on the system are skipped while they
+    /// are being indexed.
+    pub fn find(&self, c: char) -> Option<FallbackFont> {
+        if let Some(font) = self
+            .fonts
+            .iter()
+            .find(|font| font.font.lookup_glyph_index(c) != 0)
+        {
+            return Some(font.clone());
+        }
+
+        let system = self.system.lock().unwrap().get()?;
+
+        system
+            .iter()
+            .filter(|font| font.covers(c))
+            .filter_map(|font| font.load(self.scale, self.line_height))
+            .find(|font| font.font.lookup_glyph_index(c) != 0)
+    }
+}
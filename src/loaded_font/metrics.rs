@@ -0,0 +1,41 @@
+use cgmath::Vector2;
+use fontdue::Font;
+
+/// Measured to find the width of a cell, as any glyph of a monospace font would do.
+pub const REFERENCE_CHAR: char = 'M';
+
+/// The size of every cell of the grid, in whole pixels so that columns never drift.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMetrics {
+    pub size: Vector2<f32>,
+    /// The distance from the top of a cell down to the baseline glyphs sit on.
+    pub baseline: f32,
+}
+
+impl CellMetrics {
+    pub fn new(size: Vector2<f32>, baseline: f32) -> Self {
+        Self { size, baseline }
+    }
+
+    /// Cells as wide as the advance of `font` and as tall as its lines at `scale`, with half of
+    /// the line gap above the ascent and half below the descent.
+    pub fn from_font(font: &Font, scale: f32) -> Self {
+        let advance = font.metrics(REFERENCE_CHAR, scale).advance_width;
+        let (ascent, descent, line_gap) = match font.horizontal_line_metrics(scale) {
+            Some(metrics) => (metrics.ascent, metrics.descent, metrics.line_gap),
+            None => (scale, 0.0, 0.0),
+        };
+        let size = Vector2::new(
+            advance.round().max(1.0),
+            (ascent - descent + line_gap).round().max(1.0),
+        );
+
+        Self::new(size, (ascent + line_gap / 2.0).round())
+    }
+
+    /// The offset from the top left of a cell to the top left of a glyph bitmap, given the
+    /// position of the bitmap's bottom left corner relative to the origin on the baseline.
+    pub fn glyph_offset(&self, bearing: Vector2<f32>, height: f32) -> Vector2<f32> {
+        Vector2::new(bearing.x, self.baseline - bearing.y - height)
+    }
+}
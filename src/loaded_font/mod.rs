@@ -1,45 +1,211 @@
+pub mod atlas;
 pub mod chr;
+pub mod face;
+pub mod fallback;
+pub mod metrics;
 
-use crate::{terminal::config::Config, SCALE};
+use crate::{
+    item::texture::Texture,
+    terminal::{config::Config, TerminalEvent},
+};
+use anyhow::Context;
+use atlas::Atlas;
 use chr::Chr;
+use face::{Face, FontStyle};
+use fallback::{Fallback, FallbackFont, SystemFonts};
 use fontdue::{Font, FontSettings};
-use std::{fs::File, io::Read, sync::Arc};
+use metrics::CellMetrics;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    sync::{Arc, Mutex, RwLock},
+};
 use thiserror::Error;
-use vulkano::{device::Device, device::Queue};
+use unicode_width::UnicodeWidthChar;
+use vulkano::{
+    command_buffer::{
+        pool::standard::StandardCommandPoolBuilder, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    device::Queue,
+    format::Format,
+    image::ImageDimensions,
+};
+use winit::event_loop::EventLoopProxy;
+
+/// Used when the config names no font, or when the fonts it names fail to load.
+pub const BUILTIN_FONT: &[u8] = include_bytes!("../../test.ttf");
 
 pub struct LoadedFont {
-    pub chrs: Vec<Arc<Chr>>,
+    /// One face for each [`FontStyle`], in the order of [`FontStyle::ALL`].
+    pub faces: Vec<Face>,
+    pub fallback: Fallback,
+    pub atlas: RwLock<Atlas>,
+    pub texture: RwLock<Option<Texture>>,
     pub scale: f32,
+    pub cell: CellMetrics,
 }
 
 impl LoadedFont {
-    pub fn new(chrs: Vec<Arc<Chr>>, scale: f32) -> Self {
-        Self { chrs, scale }
+    pub fn new(
+        faces: Vec<Face>,
+        fallback: Fallback,
+        atlas: RwLock<Atlas>,
+        texture: RwLock<Option<Texture>>,
+        scale: f32,
+        cell: CellMetrics,
+    ) -> Self {
+        Self {
+            faces,
+            fallback,
+            atlas,
+            texture,
+            scale,
+            cell,
+        }
     }
 
+    /// Loads the fonts named by `config`. When it asks for the fonts on the system to be
+    /// searched, [`TerminalEvent::FontsIndexed`] is sent through `proxy` once they can be.
     pub fn from_file(
-        device: Arc<Device>,
-        queue: Arc<Queue>,
         config: &Config,
+        proxy: EventLoopProxy<TerminalEvent>,
+    ) -> anyhow::Result<Self> {
+        let regular = match &config.font.path {
+            Some(path) => Self::load_font(path)?,
+            None => Arc::new(Self::font_from_bytes(BUILTIN_FONT)?),
+        };
+        let bold = config.font.bold.as_ref().map(Self::load_font).transpose()?;
+        let italic = config
+            .font
+            .italic
+            .as_ref()
+            .map(Self::load_font)
+            .transpose()?;
+        let bold_italic = config
+            .font
+            .bold_italic
+            .as_ref()
+            .map(Self::load_font)
+            .transpose()?;
+        let faces = FontStyle::ALL
+            .into_iter()
+            .map(|style| Self::create_face(style, &regular, &bold, &italic, &bold_italic))
+            .collect();
+        let scale = config.font.scale;
+        let cell = CellMetrics::from_font(&regular, scale);
+        let line_height = FallbackFont::line_height(&regular, scale);
+        let mut fallback_fonts = Vec::new();
+
+        for path in &config.font.fallback {
+            fallback_fonts.push(FallbackFont::sized(
+                Self::load_font(path)?,
+                scale,
+                line_height,
+            ));
+        }
+
+        let fallback = Fallback::new(
+            fallback_fonts,
+            Mutex::new(SystemFonts::new(config.font.system_fallback, move || {
+                let _ = proxy.send_event(TerminalEvent::FontsIndexed);
+            })),
+            scale,
+            line_height,
+        );
+
+        Ok(Self::new(
+            faces,
+            fallback,
+            RwLock::new(Atlas::default()),
+            RwLock::new(None),
+            scale,
+            cell,
+        ))
+    }
+
+    /// Like [`LoadedFont::from_file`], but falls back to the built-in font with a warning when
+    /// the configured fonts cannot be loaded. The config is changed to match, so that it is not
+    /// mistaken for one using those fonts later.
+    pub fn from_file_or_default(
+        config: &mut Config,
+        proxy: EventLoopProxy<TerminalEvent>,
     ) -> anyhow::Result<Self> {
-        let bytes = Self::load_bytes(&config.font.path)?;
-        let font = Self::try_font_from_fontdue_result(Font::from_bytes(
-            bytes.as_slice(),
-            FontSettings::default(),
-        ))?;
-        let chrs = Self::create_chrs(device, queue, &font, config.font.scale);
-
-        Ok(Self::new(chrs, config.font.scale * SCALE))
+        Self::from_file(config, proxy.clone()).or_else(|e| {
+            eprintln!("Warning: {:#}, using the built-in font", e);
+
+            config.font.clear_files();
+
+            Self::from_file(config, proxy)
+        })
+    }
+
+    /// Forgets the characters no font covered, so that they are looked up again once more
+    /// fonts are available.
+    pub fn forget_missing_glyphs(&self) {
+        for face in &self.faces {
+            face.chrs.write().unwrap().retain(|_, chr| chr.is_some());
+        }
+    }
+
+    /// The glyph for `c` in `style`, rasterized the first time it is requested and taken from
+    /// the fallback fonts when the face does not cover it. Returns `None` for characters no
+    /// font covers and for glyphs without any pixels, such as spaces.
+    pub fn get_chr(&self, c: char, style: FontStyle) -> Option<Arc<Chr>> {
+        let face = self.face(style);
+
+        if let Some(chr) = face.chrs.read().unwrap().get(&c) {
+            return chr.clone();
+        }
+
+        let chr = self.create_chr(face, style, c);
+
+        face.chrs.write().unwrap().insert(c, chr.clone());
+
+        chr
     }
 
-    pub fn get_chr_by_id(&self, id: u8) -> Option<Arc<Chr>> {
-        if id >= 33 {
-            let i = id as usize - 33;
+    pub fn face(&self, style: FontStyle) -> &Face {
+        &self.faces[style as usize]
+    }
+
+    /// The atlas texture holding every glyph rasterized so far. Only the part of the atlas that
+    /// changed since the last call is copied into it, by commands recorded in `builder`, unless
+    /// the atlas grew and needs a new texture.
+    pub fn texture(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        queue: Arc<Queue>,
+    ) -> anyhow::Result<Texture> {
+        let mut atlas = self.atlas.write().unwrap();
+        let mut texture = self.texture.write().unwrap();
+        let dimensions = ImageDimensions::Dim2d {
+            width: atlas.size,
+            height: atlas.size,
+            array_layers: 1,
+        };
+
+        let texture = match &mut *texture {
+            Some(texture) if texture.dimensions() == dimensions => texture,
+            texture => {
+                let size = atlas.size;
+
+                atlas.mark_dirty(0, 0, size, size);
+
+                // Glyphs are coverage values, which must not be converted from sRGB.
+                texture.insert(Texture::empty(queue, Format::R8_UNORM, dimensions)?)
+            }
+        };
 
-            self.chrs.get(i).cloned()
-        } else {
-            None
+        if let Some((dirty, texels)) = atlas.take_dirty() {
+            texture.write(builder, [dirty.x, dirty.y], [dirty.z, dirty.w], texels)?;
         }
+
+        Ok(texture.clone())
     }
 
     fn load_bytes(path: &String) -> anyhow::Result<Vec<u8>> {
@@ -51,6 +217,47 @@ impl LoadedFont {
         Ok(buffer)
     }
 
+    fn load_font(path: &String) -> anyhow::Result<Arc<Font>> {
+        let bytes = Self::load_bytes(path).with_context(|| path.clone())?;
+        let font = Self::font_from_bytes(&bytes).with_context(|| path.clone())?;
+
+        Ok(Arc::new(font))
+    }
+
+    fn font_from_bytes(bytes: &[u8]) -> anyhow::Result<Font> {
+        Self::try_font_from_fontdue_result(Font::from_bytes(bytes, FontSettings::default()))
+    }
+
+    /// The face for `style`, given the regular font and the bold, italic and bold italic fonts
+    /// that were configured. A missing font is made up from the closest one that exists.
+    fn create_face(
+        style: FontStyle,
+        regular: &Arc<Font>,
+        bold: &Option<Arc<Font>>,
+        italic: &Option<Arc<Font>>,
+        bold_italic: &Option<Arc<Font>>,
+    ) -> Face {
+        let (font, embolden, slant) = match style {
+            FontStyle::Regular => (regular, false, false),
+            FontStyle::Bold => match bold {
+                Some(bold) => (bold, false, false),
+                None => (regular, true, false),
+            },
+            FontStyle::Italic => match italic {
+                Some(italic) => (italic, false, false),
+                None => (regular, false, true),
+            },
+            FontStyle::BoldItalic => match (bold_italic, italic, bold) {
+                (Some(bold_italic), _, _) => (bold_italic, false, false),
+                (None, Some(italic), _) => (italic, true, false),
+                (None, None, Some(bold)) => (bold, false, true),
+                (None, None, None) => (regular, true, true),
+            },
+        };
+
+        Face::new(font.clone(), embolden, slant, RwLock::new(HashMap::new()))
+    }
+
     fn try_font_from_fontdue_result(e: Result<Font, &'static str>) -> anyhow::Result<Font> {
         match e {
             Ok(f) => Ok(f),
@@ -58,29 +265,31 @@ impl LoadedFont {
         }
     }
 
-    fn create_chrs(
-        device: Arc<Device>,
-        queue: Arc<Queue>,
-        font: &Font,
-        scale: f32,
-    ) -> Vec<Arc<Chr>> {
-        (33..=126_u8)
-            .filter_map(|i| {
-                let c = i as char;
-                let (metrics, bitmap) = font.rasterize(c, scale);
-
-                match Chr::from_bitmap(i, device.clone(), queue.clone(), &metrics, &bitmap) {
-                    Ok(chr) => Some(Arc::new(chr)),
-                    Err(_) => None,
-                }
-            })
-            .collect()
-    }
-}
+    fn create_chr(&self, face: &Face, style: FontStyle, c: char) -> Option<Arc<Chr>> {
+        let (metrics, bitmap) = match face.rasterize(c, self.scale) {
+            Some(glyph) => glyph,
+            None => {
+                let fallback = self.fallback.find(c)?;
+                // Wide characters are given two cells, and nothing is narrower than one.
+                let columns = c.width().unwrap_or(1).max(1) as f32;
+
+                Face::rasterize_font(
+                    &fallback.font,
+                    c,
+                    fallback.scale_for(c, self.cell.size.x * columns),
+                    style.is_bold(),
+                    style.is_italic(),
+                )?
+            }
+        };
+
+        if metrics.width == 0 || metrics.height == 0 {
+            return None;
+        }
+
+        let mut atlas = self.atlas.write().unwrap();
 
-impl Default for LoadedFont {
-    fn default() -> Self {
-        Self::new(Vec::new(), 0.0)
+        Chr::from_bitmap(&mut atlas, &metrics, &bitmap).map(Arc::new)
     }
 }
 
@@ -1,20 +1,25 @@
+mod cli;
 mod item;
 mod loaded_font;
 mod renderer;
 mod shaders;
 mod terminal;
 
+use clap::Parser;
+use cli::Options;
+use loaded_font::LoadedFont;
 use renderer::Renderer;
 use terminal::Terminal;
+use winit::event_loop::EventLoop;
 
 pub const APP_NAME: &str = "foxterm";
-pub const SCALE: f32 = 1.0 / 1000.0;
 
-fn main() {
-    let terminal = match Terminal::init().unwrap() {
-        Some(terminal) => terminal,
-        None => return,
-    };
+fn main() -> anyhow::Result<()> {
+    let options = Options::parse();
+    let mut config = options.load_config_or_default();
+    let event_loop = EventLoop::with_user_event();
+    let font = LoadedFont::from_file_or_default(&mut config, event_loop.create_proxy())?;
+    let terminal = Terminal::init(config, font.cell)?;
 
-    Renderer::init(terminal).unwrap();
+    Renderer::init(terminal, font, event_loop, &options)
 }
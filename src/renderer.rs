@@ -1,15 +1,18 @@
 use crate::{
+    cli::Options,
     item::{
+        instance::CellInstance,
         mesh::{Mesh, Vertex},
         texture::Texture,
-        Item,
     },
-    loaded_font::LoadedFont,
-    shaders::{fragment, vertex, Shaders},
-    terminal::{drawable::RenderItem, Performer, Terminal},
+    loaded_font::{face::FontStyle, LoadedFont},
+    shaders::{vertex, Shaders},
+    terminal::{config::Config, watcher, Terminal, TerminalEvent},
     APP_NAME,
 };
 use cgmath::{Matrix4, Vector2};
+use foxterm_core::grid::{Flags, Grid};
+use notify::RecommendedWatcher;
 use std::sync::Arc;
 use vulkano::{
     buffer::{cpu_pool::CpuBufferPool, BufferUsage, TypedBufferAccess},
@@ -44,8 +47,10 @@ use vulkano::{
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
+    dpi::PhysicalSize,
+    event::{ElementState, Event, ModifiersState, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
+    platform::unix::WindowBuilderExtUnix,
     window::{Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
@@ -53,17 +58,25 @@ use winit_input_helper::WinitInputHelper;
 pub struct Renderer;
 
 impl Renderer {
-    pub fn init(terminal: Terminal) -> anyhow::Result<()> {
-        let proj = cgmath::ortho::<f32>(-1.0, 1.0, -1.0, 1.0, -1.0, 1.0);
+    pub fn init(
+        mut terminal: Terminal,
+        font: LoadedFont,
+        event_loop: EventLoop<TerminalEvent>,
+        options: &Options,
+    ) -> anyhow::Result<()> {
         let required_extensions = vulkano_win::required_extensions();
         let instance = Instance::new(InstanceCreateInfo {
             enabled_extensions: required_extensions,
             ..Default::default()
         })?;
-        let event_loop = EventLoop::new();
+        let class = options.class.as_deref().unwrap_or(APP_NAME);
         let surface = WindowBuilder::new()
-            .with_title(APP_NAME)
+            .with_title(options.title.as_deref().unwrap_or(APP_NAME))
+            .with_class(class.to_owned(), class.to_owned())
+            .with_app_id(class.to_owned())
+            .with_inner_size(terminal.window_size())
             .build_vk_surface(&event_loop, instance.clone())?;
+        let mut proj = Self::projection(surface.window().inner_size());
         let device_extensions = DeviceExtensions {
             khr_swapchain: true,
             ..DeviceExtensions::none()
@@ -167,24 +180,30 @@ impl Renderer {
         )?;
         let uniform_buffer =
             CpuBufferPool::<vertex::ty::Data>::new(device.clone(), BufferUsage::uniform_buffer());
-        let frag_uniform_buffer =
-            CpuBufferPool::<fragment::ty::Data>::new(device.clone(), BufferUsage::uniform_buffer());
-        let font = Arc::new(LoadedFont::from_file(
-            device.clone(),
-            queue.clone(),
-            &terminal.config,
-        )?);
-        let cursor = Item::new(
-            Mesh::from_rect(queue.clone(), Vector2::new(font.scale / 2.0, font.scale))?,
-            Texture::white(device.clone(), queue.clone())?,
-        );
-        let performer = terminal.spawn_reader(font);
+        let instance_buffer =
+            CpuBufferPool::<CellInstance>::new(device.clone(), BufferUsage::vertex_buffer());
+        let mut font = Arc::new(font);
+        let quad = Mesh::from_rect(queue.clone(), Vector2::new(1.0, 1.0))?;
+
+        terminal.spawn_reader(event_loop.create_proxy());
+
+        let options = options.clone();
+        let proxy = event_loop.create_proxy();
+        // Kept alive for as long as the event loop runs, and replaced when the config reloads.
+        let mut _watcher = Self::watch_config(&options, &terminal.config, proxy.clone());
+
         let write_sndr = terminal.spawn_writer();
         let mut input = WinitInputHelper::new();
+        let mut modifiers = ModifiersState::empty();
+        let mut suppress_chars = false;
+        let mut exited = false;
+        let mut rows = Vec::new();
         let mut recreate_swapchain = false;
         let mut previous_frame_end = Some(sync::now(device.clone()).boxed());
 
         event_loop.run(move |event, _, control_flow| {
+            *control_flow = ControlFlow::Wait;
+
             input.update(&event);
 
             match event {
@@ -193,12 +212,75 @@ impl Renderer {
                     ..
                 } => *control_flow = ControlFlow::Exit,
                 Event::WindowEvent {
-                    event: WindowEvent::Resized(_),
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    if let Err(e) = terminal.resize(size) {
+                        println!("Failed to resize terminal: {:?}", e);
+                    }
+
+                    recreate_swapchain = true;
+
+                    surface.window().request_redraw();
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ModifiersChanged(state),
+                    ..
+                } => modifiers = state,
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput { input, .. },
                     ..
-                } => recreate_swapchain = true,
-                Event::RedrawEventsCleared => {
-                    terminal.update_pty(&write_sndr, &input).unwrap();
+                } => {
+                    suppress_chars = match (input.state, input.virtual_keycode) {
+                        (ElementState::Pressed, Some(key)) if !exited => {
+                            terminal.key_input(&write_sndr, key, modifiers).unwrap()
+                        }
+                        _ => false,
+                    };
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ReceivedCharacter(c),
+                    ..
+                } if !suppress_chars && !exited => {
+                    terminal.received_char(&write_sndr, c, modifiers).unwrap()
+                }
+                // The reader only has to wake the loop, damage is checked below.
+                Event::UserEvent(TerminalEvent::Wakeup) => {}
+                Event::UserEvent(TerminalEvent::Exit(_)) if terminal.config.close_on_exit => {
+                    *control_flow = ControlFlow::Exit
+                }
+                Event::UserEvent(TerminalEvent::Exit(code)) => {
+                    terminal.show_exit(code);
+
+                    exited = true;
+                }
+                Event::UserEvent(TerminalEvent::ConfigChanged) => {
+                    match Self::reload_config(
+                        &mut terminal,
+                        &mut font,
+                        &options,
+                        surface.window().inner_size(),
+                        proxy.clone(),
+                    ) {
+                        // The imports or the theme may have changed along with the config.
+                        Ok(()) => {
+                            _watcher = Self::watch_config(&options, &terminal.config, proxy.clone())
+                        }
+                        Err(e) => eprintln!("Failed to reload config: {:?}", e),
+                    }
+                }
+                Event::UserEvent(TerminalEvent::FontsIndexed) => {
+                    font.forget_missing_glyphs();
+                    terminal.performer.write().unwrap().grid.damage_all();
+                }
+                Event::MainEventsCleared => {
+                    terminal.update_viewport(&input);
 
+                    if terminal.is_damaged() {
+                        surface.window().request_redraw();
+                    }
+                }
+                Event::RedrawRequested(_) => {
                     previous_frame_end.as_mut().unwrap().cleanup_finished();
 
                     if recreate_swapchain {
@@ -226,6 +308,7 @@ impl Renderer {
 
                         pipeline = new_pipeline;
                         framebuffers = new_framebuffers;
+                        proj = Self::projection(surface.window().inner_size());
                         recreate_swapchain = false;
                     }
 
@@ -236,6 +319,8 @@ impl Renderer {
                             Err(AcquireError::OutOfDate) => {
                                 recreate_swapchain = true;
 
+                                surface.window().request_redraw();
+
                                 return;
                             }
 
@@ -253,24 +338,39 @@ impl Renderer {
                     )
                     .unwrap();
 
+                    let instances = {
+                        let mut performer = terminal.performer.write().unwrap();
+
+                        Self::update_rows(&mut rows, &mut performer.grid, &font, &terminal);
+
+                        rows.concat()
+                    };
+                    // Rasterizing the cells may have added glyphs, so the atlas is fetched
+                    // afterwards. Uploading them has to happen before the render pass begins.
+                    let atlas = font.texture(&mut builder, queue.clone()).unwrap();
+
                     builder
                         .begin_render_pass(
                             framebuffers[image_num].clone(),
                             SubpassContents::Inline,
-                            vec![terminal.config.bg_color.into(), 1_f32.into()],
+                            vec![terminal.config.background().into(), 1_f32.into()],
                         )
                         .unwrap();
 
-                    Self::draw_terminal(
-                        &mut builder,
-                        pipeline.clone(),
-                        &uniform_buffer,
-                        &frag_uniform_buffer,
-                        &performer.read().unwrap(),
-                        &cursor,
-                        proj,
-                        &terminal,
-                    );
+                    if !instances.is_empty() {
+                        Self::draw_terminal(
+                            &mut builder,
+                            pipeline.clone(),
+                            &uniform_buffer,
+                            &instance_buffer,
+                            instances,
+                            &quad,
+                            &font,
+                            atlas,
+                            proj,
+                        )
+                        .unwrap();
+                    }
 
                     builder.end_render_pass().unwrap();
 
@@ -291,6 +391,9 @@ impl Renderer {
                         Err(FlushError::OutOfDate) => {
                             recreate_swapchain = true;
                             previous_frame_end = Some(sync::now(device.clone()).boxed());
+
+                            // Nothing else may damage the screen, so draw it again right away.
+                            surface.window().request_redraw();
                         }
                         Err(e) => {
                             println!("Failed to flush future: {:?}", e);
@@ -304,91 +407,77 @@ impl Renderer {
         });
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn draw_terminal(
-        builder: &mut AutoCommandBufferBuilder<
-            PrimaryAutoCommandBuffer,
-            StandardCommandPoolBuilder,
-        >,
-        pipeline: Arc<GraphicsPipeline>,
-        uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
-        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
-        performer: &Performer,
-        cursor: &Item,
-        proj: Matrix4<f32>,
-        terminal: &Terminal,
-    ) {
-        for drawable in &*terminal.screen.read().unwrap() {
-            if let RenderItem::Chr(chr) = &drawable.render_item {
-                Self::draw_item(
-                    builder,
-                    pipeline.clone(),
-                    terminal,
-                    uniform_buffer,
-                    frag_uniform_buffer,
-                    proj,
-                    drawable.pos,
-                    &chr.item,
-                );
-            }
+    /// Loads the config file again and applies it, rebuilding the font when its files or scale
+    /// changed. Nothing is applied when the new config or font fails to load.
+    fn reload_config(
+        terminal: &mut Terminal,
+        font: &mut Arc<LoadedFont>,
+        options: &Options,
+        size: PhysicalSize<u32>,
+        proxy: EventLoopProxy<TerminalEvent>,
+    ) -> anyhow::Result<()> {
+        let config = options.load_config()?;
+        let old_font = &terminal.config.font;
+
+        if !config.font.same_glyphs(old_font) {
+            *font = Arc::new(LoadedFont::from_file(&config, proxy)?);
         }
 
-        Self::draw_item(
-            builder,
-            pipeline,
-            terminal,
-            uniform_buffer,
-            frag_uniform_buffer,
-            proj,
-            performer.pos,
-            cursor,
-        );
+        terminal.set_config(config, font.cell, size)
     }
 
+    /// Watches the config file chosen by `options` and every file `config` was loaded from, so
+    /// that editing any of them reloads the config.
+    fn watch_config(
+        options: &Options,
+        config: &Config,
+        proxy: EventLoopProxy<TerminalEvent>,
+    ) -> Option<RecommendedWatcher> {
+        let mut paths = vec![options.config_path()];
+
+        paths.extend(config.files.iter().cloned());
+
+        match watcher::watch_config(&paths, proxy) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                eprintln!("Failed to watch config: {:?}", e);
+
+                None
+            }
+        }
+    }
+
+    /// Draws every cell of the grid with a single instanced draw of `quad`.
     #[allow(clippy::too_many_arguments)]
-    fn draw_item(
+    fn draw_terminal(
         builder: &mut AutoCommandBufferBuilder<
             PrimaryAutoCommandBuffer,
             StandardCommandPoolBuilder,
         >,
         pipeline: Arc<GraphicsPipeline>,
-        terminal: &Terminal,
         uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
-        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        instance_buffer: &CpuBufferPool<CellInstance>,
+        instances: Vec<CellInstance>,
+        quad: &Mesh,
+        font: &LoadedFont,
+        atlas: Texture,
         proj: Matrix4<f32>,
-        pos: Vector2<f32>,
-        item: &Item,
-    ) {
-        let uniform_buffer_subbuffer = {
-            let uniform_data = vertex::ty::Data {
-                proj: proj.into(),
-                transform: Matrix4::from_translation(pos.extend(0.0)).into(),
-            };
-
-            Arc::new(uniform_buffer.next(uniform_data).unwrap())
-        };
-        let frag_uniform_buffer_subbuffer = {
-            let uniform_data = fragment::ty::Data {
-                color: terminal.config.font_color,
-            };
-
-            Arc::new(frag_uniform_buffer.next(uniform_data).unwrap())
-        };
+    ) -> anyhow::Result<()> {
+        let instance_count = instances.len() as u32;
+        let instance_buffer_chunk = instance_buffer.chunk(instances)?;
+        let uniform_buffer_subbuffer = uniform_buffer.next(vertex::ty::Data {
+            proj: proj.into(),
+            cell_size: font.cell.size.into(),
+        })?;
         let descriptor_set_layouts = pipeline.layout().set_layouts();
-        let set_layout = descriptor_set_layouts.get(0).unwrap();
+        let set_layout = descriptor_set_layouts.first().unwrap();
         let set = PersistentDescriptorSet::new(
             set_layout.clone(),
             [
                 WriteDescriptorSet::buffer(0, uniform_buffer_subbuffer),
-                WriteDescriptorSet::buffer(1, frag_uniform_buffer_subbuffer),
-                WriteDescriptorSet::image_view_sampler(
-                    2,
-                    item.texture.image.clone(),
-                    item.texture.sampler.clone(),
-                ),
+                WriteDescriptorSet::image_view_sampler(1, atlas.image, atlas.sampler),
             ],
-        )
-        .unwrap();
+        )?;
 
         builder
             .bind_pipeline_graphics(pipeline.clone())
@@ -398,10 +487,103 @@ impl Renderer {
                 0,
                 set,
             )
-            .bind_vertex_buffers(0, item.mesh.vertices.clone())
-            .bind_index_buffer(item.mesh.indices.clone())
-            .draw_indexed(item.mesh.indices.len() as u32, 1, 0, 0, 0)
-            .unwrap();
+            .bind_vertex_buffers(0, (quad.vertices.clone(), instance_buffer_chunk))
+            .bind_index_buffer(quad.indices.clone())
+            .draw_indexed(quad.indices.len() as u32, instance_count, 0, 0, 0)?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the instances of the rows of `grid` damaged since the last frame and resets
+    /// the damage. `rows` keeps the instances of every row between frames.
+    fn update_rows(
+        rows: &mut Vec<Vec<CellInstance>>,
+        grid: &mut Grid,
+        font: &LoadedFont,
+        terminal: &Terminal,
+    ) {
+        if rows.len() != grid.rows {
+            rows.resize(grid.rows, Vec::new());
+            grid.damage_all();
+        }
+
+        for (row, instances) in rows.iter_mut().enumerate() {
+            if grid.damage[row] {
+                *instances = Self::create_instances(grid, row, font, terminal);
+            }
+        }
+
+        grid.reset_damage();
+    }
+
+    /// Builds one instance for every cell in `row` of the viewport, skipping the spacers behind
+    /// wide characters since the wide character's instance covers them. The cell under the
+    /// cursor has its colours replaced to draw a block cursor.
+    fn create_instances(
+        grid: &Grid,
+        row: usize,
+        font: &LoadedFont,
+        terminal: &Terminal,
+    ) -> Vec<CellInstance> {
+        let cursor = (grid.cursor.row + grid.display_offset, grid.cursor.col);
+        let mut instances = Vec::with_capacity(grid.cols);
+
+        for (col, cell) in grid.display_line(row).iter().enumerate() {
+            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                continue;
+            }
+
+            let (fg, bg) = if (row, col) == cursor {
+                (terminal.config.background(), terminal.config.cursor_color())
+            } else {
+                terminal.cell_colors(cell)
+            };
+            let style = FontStyle::new(
+                cell.flags.contains(Flags::BOLD),
+                cell.flags.contains(Flags::ITALIC),
+            );
+            let (glyph, uv_rect) = match font.get_chr(cell.c, style) {
+                Some(chr) => {
+                    let offset = font.cell.glyph_offset(chr.bearing, chr.dimensions.y);
+
+                    (
+                        [offset.x, offset.y, chr.dimensions.x, chr.dimensions.y],
+                        chr.uv.into(),
+                    )
+                }
+                None => ([0.0; 4], [0.0; 4]),
+            };
+            let mut flags = 0;
+
+            if cell.flags.contains(Flags::WIDE_CHAR) {
+                flags |= CellInstance::WIDE_CHAR;
+            }
+
+            if cell.flags.contains(Flags::UNDERLINE) {
+                flags |= CellInstance::UNDERLINE;
+            }
+
+            if cell.flags.contains(Flags::STRIKEOUT) {
+                flags |= CellInstance::STRIKEOUT;
+            }
+
+            instances.push(CellInstance::new(
+                [col as f32, row as f32],
+                glyph,
+                uv_rect,
+                fg,
+                bg,
+                flags,
+            ));
+        }
+
+        instances
+    }
+
+    /// Maps pixel coordinates with the origin in the top left corner of the window to
+    /// normalized device coordinates.
+    fn projection(size: PhysicalSize<u32>) -> Matrix4<f32> {
+        cgmath::ortho(0.0, size.width as f32, 0.0, size.height as f32, -1.0, 1.0)
     }
 
     fn window_size_dependent_setup(
@@ -433,10 +615,14 @@ impl Renderer {
             .collect();
         let subpass = Subpass::from(render_pass, 0).unwrap();
         let pipeline = GraphicsPipeline::start()
-            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<CellInstance>(),
+            )
             .vertex_shader(shaders.vertex.entry_point("main").unwrap(), ())
             .input_assembly_state(
-                InputAssemblyState::new().topology(PrimitiveTopology::TriangleStrip),
+                InputAssemblyState::new().topology(PrimitiveTopology::TriangleList),
             )
             .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([
                 Viewport {
@@ -446,7 +632,7 @@ impl Renderer {
                 },
             ]))
             .fragment_shader(shaders.fragment.entry_point("main").unwrap(), ())
-            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .depth_stencil_state(DepthStencilState::disabled())
             .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
             .render_pass(subpass)
             .build(device)?;
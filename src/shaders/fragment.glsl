@@ -1,14 +1,43 @@
 #version 450
 
-layout(location = 0) in vec2 tex_coord;
+// Must match the flags on `CellInstance`.
+const uint UNDERLINE = 2;
+const uint STRIKEOUT = 4;
+
+// Underlines and strikethroughs are drawn this many times thinner than a cell is tall.
+const float LINE_THICKNESS_DIVISOR = 16.0;
+
+layout(location = 0) in vec2 local_pos;
+layout(location = 1) flat in vec2 size;
+layout(location = 2) flat in vec4 glyph;
+layout(location = 3) flat in vec4 uv_rect;
+layout(location = 4) flat in vec4 fg;
+layout(location = 5) flat in vec4 bg;
+layout(location = 6) flat in uint flags;
 
 layout(location = 0) out vec4 f_color;
 
-layout(set = 0, binding = 2) uniform sampler2D tex;
-layout(set = 0, binding = 1) uniform Data {
-    	vec4 color;
-} uniforms;
+layout(set = 0, binding = 1) uniform sampler2D atlas;
 
 void main() {
-    	f_color = texture(tex, tex_coord).r * uniforms.color;
+	float coverage = 0.0;
+	vec2 glyph_pos = local_pos - glyph.xy;
+
+	if (all(greaterThanEqual(glyph_pos, vec2(0.0))) && all(lessThan(glyph_pos, glyph.zw))) {
+		vec2 tex_coord = uv_rect.xy + glyph_pos / glyph.zw * uv_rect.zw;
+
+		coverage = texture(atlas, tex_coord / vec2(textureSize(atlas, 0))).r;
+	}
+
+	float thickness = size.y / LINE_THICKNESS_DIVISOR;
+
+	if ((flags & UNDERLINE) != 0 && local_pos.y >= size.y - thickness) {
+		coverage = 1.0;
+	}
+
+	if ((flags & STRIKEOUT) != 0 && local_pos.y >= size.y / 2.0 && local_pos.y < size.y / 2.0 + thickness) {
+		coverage = 1.0;
+	}
+
+	f_color = mix(bg, fg, coverage);
 }
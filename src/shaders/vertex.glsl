@@ -1,16 +1,41 @@
 #version 450
 
+// Must match the flags on `CellInstance`.
+const uint WIDE_CHAR = 1;
+
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 uv;
 
-layout(location = 0) out vec2 tex_coords;
+layout(location = 2) in vec2 cell;
+layout(location = 3) in vec4 glyph;
+layout(location = 4) in vec4 uv_rect;
+layout(location = 5) in vec4 fg;
+layout(location = 6) in vec4 bg;
+layout(location = 7) in uint flags;
+
+layout(location = 0) out vec2 local_pos;
+layout(location = 1) flat out vec2 v_size;
+layout(location = 2) flat out vec4 v_glyph;
+layout(location = 3) flat out vec4 v_uv_rect;
+layout(location = 4) flat out vec4 v_fg;
+layout(location = 5) flat out vec4 v_bg;
+layout(location = 6) flat out uint v_flags;
 
 layout(set = 0, binding = 0) uniform Data {
-    	mat4 proj;
-	mat4 transform;
+	mat4 proj;
+	vec2 cell_size;
 } uniforms;
 
 void main() {
-	tex_coords = uv;
-	gl_Position = uniforms.proj * (uniforms.transform * vec4(position, 1.0));
+	float width = (flags & WIDE_CHAR) != 0 ? 2.0 : 1.0;
+
+	v_size = uniforms.cell_size * vec2(width, 1.0);
+	local_pos = uv * v_size;
+	v_glyph = glyph;
+	v_uv_rect = uv_rect;
+	v_fg = fg;
+	v_bg = bg;
+	v_flags = flags;
+
+	gl_Position = uniforms.proj * vec4(cell * uniforms.cell_size + local_pos, position.z, 1.0);
 }
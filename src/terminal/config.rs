@@ -1,75 +1,580 @@
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context};
+use foxterm_core::{
+    color::{Palette, Rgb, ANSI_COLORS},
+    pty::ShellCommand,
+};
+use serde::{
+    de::{self, DeserializeOwned, SeqAccess, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_yaml::{Mapping, Value};
 use std::{
+    collections::BTreeMap,
+    env, fmt,
     fs::{self, File},
-    io::{self, ErrorKind, Read, Write},
-    path::Path,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
+use thiserror::Error;
+
+/// The directory holding foxterm's config inside each XDG config directory.
+pub const CONFIG_DIR_NAME: &str = "foxterm";
+pub const CONFIG_FILE_NAME: &str = "config.yaml";
+/// Searched for a config after `$XDG_CONFIG_DIRS`, even when that is set.
+pub const SYSTEM_CONFIG_DIR: &str = "/etc/xdg";
+/// The directory next to the config holding the files named by `theme`.
+pub const THEMES_DIR_NAME: &str = "themes";
+
+/// A colour written either as `"#rrggbb"` or `"#rrggbbaa"`, or as a list of 3 or 4 numbers
+/// between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Rgba(pub [f32; 4]);
+
+impl Rgba {
+    fn from_hex(s: &str) -> Option<Self> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.is_ascii() && matches!(hex.len(), 6 | 8))?;
+        let mut color = [1.0; 4];
+
+        for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+            *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()? as f32 / 255.0;
+        }
+
+        Some(Self(color))
+    }
+
+    fn is_valid(&self) -> bool {
+        self.0.iter().all(|c| (0.0..=1.0).contains(c))
+    }
+
+    /// The colour without its alpha, as used by the palette.
+    pub fn to_rgb(self) -> Rgb {
+        let [r, g, b, _] = self.0.map(|c| (c * 255.0).round() as u8);
+
+        Rgb::new(r, g, b)
+    }
+}
+
+impl From<Rgba> for [f32; 4] {
+    fn from(color: Rgba) -> Self {
+        color.0
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgba {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RgbaVisitor)
+    }
+}
+
+struct RgbaVisitor;
+
+impl<'de> Visitor<'de> for RgbaVisitor {
+    type Value = Rgba;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a colour as `#rrggbb`, `#rrggbbaa` or a list of 3 or 4 numbers")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Rgba, E> {
+        Rgba::from_hex(s).ok_or_else(|| E::invalid_value(Unexpected::Str(s), &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Rgba, A::Error> {
+        let mut color = [1.0; 4];
+        let mut len = 0;
 
-pub const DEFAULT_CONFIG_DIR: &str = "~/.config/foxterm/config.yaml";
+        while let Some(c) = seq.next_element::<f32>()? {
+            if len == color.len() {
+                return Err(de::Error::invalid_length(len + 1, &self));
+            }
+
+            color[len] = c;
+            len += 1;
+        }
+
+        if len < 3 {
+            return Err(de::Error::invalid_length(len, &self));
+        }
+
+        Ok(Rgba(color))
+    }
+}
+
+/// Colours left out fall back to `font.color`, `bg_color` and the xterm palette.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<Rgba>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<Rgba>,
+    /// Defaults to the foreground.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<Rgba>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Rgba>,
+    /// Replaces the first of the 16 ANSI colours, in order from black to bright white. These
+    /// are also the first 16 entries of the 256 colour table.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ansi: Vec<Rgba>,
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Font {
-    pub path: String,
-    pub color: [f32; 4],
+    /// Defaults to the font built into foxterm.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The faces used for bold and italic text. A missing face is made up by emboldening or
+    /// slanting the closest one there is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bold: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub italic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bold_italic: Option<String>,
+    /// Fonts searched in order for characters the faces above do not cover.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback: Vec<String>,
+    /// Whether to search the fonts installed on the system for characters no configured font
+    /// covers.
+    pub system_fallback: bool,
+    pub color: Rgba,
     pub scale: f32,
 }
 
 impl Font {
-    pub fn new(path: String, color: [f32; 4], scale: f32) -> Self {
-        Self { path, color, scale }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: Option<String>,
+        bold: Option<String>,
+        italic: Option<String>,
+        bold_italic: Option<String>,
+        fallback: Vec<String>,
+        system_fallback: bool,
+        color: Rgba,
+        scale: f32,
+    ) -> Self {
+        Self {
+            path,
+            bold,
+            italic,
+            bold_italic,
+            fallback,
+            system_fallback,
+            color,
+            scale,
+        }
+    }
+
+    /// Whether `other` needs the glyphs rasterized again, because it uses other font files or
+    /// another scale.
+    pub fn same_glyphs(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.bold == other.bold
+            && self.italic == other.italic
+            && self.bold_italic == other.bold_italic
+            && self.fallback == other.fallback
+            && self.system_fallback == other.system_fallback
+            && self.scale == other.scale
+    }
+
+    /// Forgets every configured font file, leaving only the built-in font.
+    pub fn clear_files(&mut self) {
+        self.path = None;
+        self.bold = None;
+        self.italic = None;
+        self.bold_italic = None;
+        self.fallback.clear();
     }
 }
 
 impl Default for Font {
     fn default() -> Self {
-        Self::new("test.ttf".to_owned(), [1.0; 4], 40.0)
+        Self::new(
+            None,
+            None,
+            None,
+            None,
+            Vec::new(),
+            false,
+            Rgba([1.0; 4]),
+            40.0,
+        )
+    }
+}
+
+/// Run when neither the config nor `$SHELL` name a shell.
+pub const FALLBACK_SHELL: &str = "/bin/sh";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shell {
+    /// Defaults to `$SHELL`.
+    pub program: Option<String>,
+    pub args: Vec<String>,
+    pub login: bool,
+    pub working_directory: Option<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl Shell {
+    pub fn command(&self) -> ShellCommand {
+        let program = self
+            .program
+            .clone()
+            .or_else(|| env::var("SHELL").ok())
+            .unwrap_or_else(|| FALLBACK_SHELL.to_owned());
+        let working_directory = self
+            .working_directory
+            .as_ref()
+            .map(|dir| PathBuf::from(shellexpand::tilde(dir).as_ref()));
+
+        ShellCommand::new(
+            program,
+            self.args.clone(),
+            self.login,
+            working_directory,
+            self.env.clone().into_iter().collect(),
+        )
     }
 }
 
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10000;
+
+/// Every field may be left out of the config file, taking its default value.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub device_index: Option<usize>,
-    pub bg_color: [f32; 4],
+    pub bg_color: Rgba,
     pub font: Font,
+    /// A file in the themes directory next to the config, without its `.yaml` extension,
+    /// whose colours are used where the `colors` section leaves them out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    pub colors: Colors,
+    pub scrollback_lines: usize,
+    /// Whether the window closes when the shell exits, instead of showing its exit code.
+    pub close_on_exit: bool,
+    pub shell: Shell,
+    /// Config files loaded underneath this one, relative to its directory. Their sections are
+    /// merged, with later files overriding earlier ones and this file overriding them all.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub import: Vec<String>,
+    /// The files the config was loaded from: the config file, its imports and its theme.
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
 }
 
 impl Config {
-    pub fn new(device_index: Option<usize>, bg_color: [f32; 4], font: Font) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device_index: Option<usize>,
+        bg_color: Rgba,
+        font: Font,
+        theme: Option<String>,
+        colors: Colors,
+        scrollback_lines: usize,
+        close_on_exit: bool,
+        shell: Shell,
+        import: Vec<String>,
+        files: Vec<PathBuf>,
+    ) -> Self {
         Self {
             device_index,
             bg_color,
             font,
+            theme,
+            colors,
+            scrollback_lines,
+            close_on_exit,
+            shell,
+            import,
+            files,
+        }
+    }
+
+    /// Loads the config at `path` together with its imports and applies `overrides` on top of
+    /// it. Values that are out of range are replaced by their defaults, printing a warning for
+    /// each.
+    pub fn from_file(path: &Path, overrides: &[Override]) -> anyhow::Result<Self> {
+        let (mut value, mut files) = Self::load_value(path, &mut Vec::new())?;
+
+        for o in overrides {
+            o.apply(&mut value)?;
         }
+
+        files.extend(Self::apply_theme(path, &mut value)?);
+
+        let mut config: Self = serde_yaml::from_value(value).map_err(ConfigError::from_yaml)?;
+
+        config.files = files;
+
+        for warning in config.validate() {
+            eprintln!("Warning: {}: {}", path.display(), warning);
+        }
+
+        Ok(config)
+    }
+
+    pub fn foreground(&self) -> [f32; 4] {
+        self.colors.foreground.unwrap_or(self.font.color).into()
+    }
+
+    pub fn background(&self) -> [f32; 4] {
+        self.colors.background.unwrap_or(self.bg_color).into()
+    }
+
+    pub fn cursor_color(&self) -> [f32; 4] {
+        self.colors
+            .cursor
+            .map(Into::into)
+            .unwrap_or_else(|| self.foreground())
+    }
+
+    /// The 256 colour table, starting with the configured ANSI colours.
+    pub fn palette(&self) -> Palette {
+        let mut ansi = ANSI_COLORS;
+
+        for (color, configured) in ansi.iter_mut().zip(&self.colors.ansi) {
+            *color = configured.to_rgb();
+        }
+
+        Palette::new(ansi)
+    }
+
+    /// The user's config file, `$XDG_CONFIG_HOME/foxterm/config.yaml`.
+    pub fn user_path() -> PathBuf {
+        Self::config_home()
+            .join(CONFIG_DIR_NAME)
+            .join(CONFIG_FILE_NAME)
+    }
+
+    /// The first config file that exists, looking in `$XDG_CONFIG_HOME`, then in each of
+    /// `$XDG_CONFIG_DIRS` and finally in [`SYSTEM_CONFIG_DIR`].
+    pub fn find_path() -> Option<PathBuf> {
+        let mut dirs = vec![Self::config_home()];
+
+        dirs.extend(Self::config_dirs());
+
+        dirs.into_iter()
+            .map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// `$XDG_CONFIG_HOME`, defaulting to `~/.config`.
+    fn config_home() -> PathBuf {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.config").as_ref()))
+    }
+
+    /// `$XDG_CONFIG_DIRS`, followed by [`SYSTEM_CONFIG_DIR`] when it is not already listed.
+    /// Relative paths are ignored, as the specification requires.
+    fn config_dirs() -> Vec<PathBuf> {
+        let mut dirs = env::var_os("XDG_CONFIG_DIRS")
+            .map(|dirs| env::split_paths(&dirs).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        dirs.retain(|dir| dir.is_absolute());
+
+        if !dirs.iter().any(|dir| dir == Path::new(SYSTEM_CONFIG_DIR)) {
+            dirs.push(PathBuf::from(SYSTEM_CONFIG_DIR));
+        }
+
+        dirs
+    }
+
+    /// Parses the file at `path`, merged over the files it imports, together with every file
+    /// that was read. `stack` holds the files currently being loaded, to catch imports that
+    /// lead back to themselves.
+    fn load_value(path: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<(Value, Vec<PathBuf>)> {
+        let name = path.display().to_string();
+        let mut value = Self::read_value::<Self>(path)?;
+        let mut files = vec![path.to_owned()];
+        let imports = match &mut value {
+            Value::Mapping(mapping) => mapping.remove(&Value::from("import")),
+            _ => None,
+        };
+        let mut merged = Value::Mapping(Mapping::new());
+
+        if let Some(imports) = imports {
+            let canonical = fs::canonicalize(path)?;
+
+            if stack.contains(&canonical) {
+                return Err(ConfigError::ImportCycle(name).into());
+            }
+
+            stack.push(canonical);
+
+            for import in serde_yaml::from_value::<Vec<String>>(imports)? {
+                let import = shellexpand::tilde(&import);
+                let import = path.parent().unwrap().join(import.as_ref());
+
+                let (import, import_files) = Self::load_value(&import, stack)?;
+
+                Self::merge(&mut merged, import);
+                files.extend(import_files);
+            }
+
+            stack.pop();
+        }
+
+        Self::merge(&mut merged, value);
+
+        Ok((merged, files))
+    }
+
+    /// Parses the file at `path`, checking that it holds a `T` so that errors point into it.
+    fn read_value<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Value> {
+        let name = path.display().to_string();
+        let contents = fs::read_to_string(path).with_context(|| name.clone())?;
+
+        if Self::is_empty(&contents) {
+            return Ok(Value::Mapping(Mapping::new()));
+        }
+
+        // Deserializing the text directly keeps the location of any error.
+        serde_yaml::from_str::<T>(&contents)
+            .map_err(ConfigError::from_yaml)
+            .with_context(|| name)?;
+
+        Ok(serde_yaml::from_str(&contents)?)
     }
 
-    pub fn from_file(path: &String) -> anyhow::Result<Self> {
-        match Self::load_contents(path) {
-            Ok(contents) => {
-                let config = serde_yaml::from_str(contents.as_str())?;
+    /// Merges the colours of the theme named in `value` underneath its `colors` section,
+    /// returning the theme file. Themes are looked up in the themes directory next to the
+    /// config at `path`.
+    fn apply_theme(path: &Path, value: &mut Value) -> anyhow::Result<Option<PathBuf>> {
+        let name = match value.get("theme") {
+            Some(Value::String(name)) => name,
+            _ => return Ok(None),
+        };
+        let theme_path = path
+            .parent()
+            .unwrap()
+            .join(THEMES_DIR_NAME)
+            .join(format!("{}.yaml", name));
+        let mut colors = Self::read_value::<Colors>(&theme_path)?;
 
-                Ok(config)
+        if let Value::Mapping(mapping) = value {
+            if let Some(own) = mapping.remove(&Value::from("colors")) {
+                Self::merge(&mut colors, own);
             }
-            Err(e) => match e.downcast_ref::<io::Error>().and_then(|e| Some(e.kind())) {
-                Some(ErrorKind::NotFound) => {
-                    let config = Self::default();
 
-                    config.create_file(&DEFAULT_CONFIG_DIR.to_string())?;
+            mapping.insert("colors".into(), colors);
+        }
 
-                    Ok(config)
+        Ok(Some(theme_path))
+    }
+
+    /// Merges `value` into `base`, recursing into sections both have and replacing anything
+    /// else.
+    fn merge(base: &mut Value, value: Value) {
+        match (base, value) {
+            (Value::Mapping(base), Value::Mapping(value)) => {
+                for (key, value) in value {
+                    match base.get_mut(&key) {
+                        Some(base) => Self::merge(base, value),
+                        None => {
+                            base.insert(key, value);
+                        }
+                    }
                 }
-                _ => Err(e.into()),
-            },
+            }
+            (base, value) => *base = value,
+        }
+    }
+
+    /// Replaces values that are out of range with their defaults, returning why each was
+    /// replaced.
+    pub fn validate(&mut self) -> Vec<ConfigError> {
+        let default = Self::default();
+        let mut warnings = Vec::new();
+
+        if !self.bg_color.is_valid() {
+            warnings.push(ConfigError::OutOfRange("bg_color", "between 0 and 1"));
+            self.bg_color = default.bg_color;
+        }
+
+        if !self.font.color.is_valid() {
+            warnings.push(ConfigError::OutOfRange("font.color", "between 0 and 1"));
+            self.font.color = default.font.color;
+        }
+
+        let colors = [
+            ("colors.foreground", &mut self.colors.foreground),
+            ("colors.background", &mut self.colors.background),
+            ("colors.cursor", &mut self.colors.cursor),
+            ("colors.selection", &mut self.colors.selection),
+        ];
+
+        for (name, color) in colors {
+            if matches!(color, Some(color) if !color.is_valid()) {
+                warnings.push(ConfigError::OutOfRange(name, "between 0 and 1"));
+                *color = None;
+            }
+        }
+
+        if self.colors.ansi.len() > ANSI_COLORS.len() {
+            warnings.push(ConfigError::OutOfRange("colors.ansi", "at most 16 colours"));
+            self.colors.ansi.truncate(ANSI_COLORS.len());
+        }
+
+        if !self.colors.ansi.iter().all(Rgba::is_valid) {
+            warnings.push(ConfigError::OutOfRange("colors.ansi", "between 0 and 1"));
+            self.colors.ansi.clear();
+        }
+
+        if !self.font.scale.is_finite() || self.font.scale <= 0.0 {
+            warnings.push(ConfigError::OutOfRange("font.scale", "greater than 0"));
+            self.font.scale = default.font.scale;
+        }
+
+        let faces = [
+            &mut self.font.path,
+            &mut self.font.bold,
+            &mut self.font.italic,
+            &mut self.font.bold_italic,
+        ];
+
+        for face in faces {
+            if matches!(face, Some(path) if !Self::is_file(path)) {
+                warnings.push(ConfigError::MissingFont(face.take().unwrap()));
+            }
+        }
+
+        for path in &self.font.fallback {
+            if !Self::is_file(path) {
+                warnings.push(ConfigError::MissingFont(path.clone()));
+            }
         }
+
+        self.font.fallback.retain(|path| Self::is_file(path));
+
+        warnings
     }
 
-    pub fn default_from_file() -> anyhow::Result<Self> {
-        Self::from_file(&shellexpand::tilde(DEFAULT_CONFIG_DIR).as_ref().to_string())
+    /// Whether a config file holds nothing but comments, which YAML cannot parse.
+    fn is_empty(contents: &str) -> bool {
+        contents
+            .lines()
+            .map(str::trim)
+            .all(|line| line.is_empty() || line.starts_with('#'))
     }
 
-    fn create_file(&self, path: &String) -> anyhow::Result<()> {
-        let path = shellexpand::tilde(path);
-        let path = Path::new(path.as_ref());
+    /// Whether a path from the config, which may start with `~`, names an existing file.
+    fn is_file(path: &str) -> bool {
+        Path::new(shellexpand::tilde(path).as_ref()).is_file()
+    }
 
+    /// Writes the config to `path`, creating the directories leading to it.
+    pub fn create_file(&self, path: &Path) -> anyhow::Result<()> {
         fs::create_dir_all(path.parent().unwrap())?;
 
         let mut file = File::create(path)?;
@@ -78,19 +583,397 @@ impl Config {
 
         Ok(())
     }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(
+            None,
+            Rgba([0.0; 4]),
+            Default::default(),
+            None,
+            Default::default(),
+            DEFAULT_SCROLLBACK_LINES,
+            true,
+            Default::default(),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+}
+
+/// A single config value set from the command line, as in `font.scale=28`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    /// The keys leading to the value, one per level of nesting.
+    pub path: Vec<String>,
+    pub value: Value,
+}
+
+impl Override {
+    pub fn new(path: Vec<String>, value: Value) -> Self {
+        Self { path, value }
+    }
+
+    /// Sets the value in a parsed config file, creating the sections leading to it as needed.
+    pub fn apply(&self, config: &mut Value) -> anyhow::Result<()> {
+        let mut value = config;
+
+        for key in &self.path {
+            if value.is_null() {
+                *value = Value::Mapping(Mapping::new());
+            }
+
+            value = match value {
+                Value::Mapping(mapping) => {
+                    mapping.entry(key.as_str().into()).or_insert(Value::Null)
+                }
+                _ => return Err(anyhow!("`{}` is not a section", key)),
+            };
+        }
+
+        *value = self.value.clone();
+
+        Ok(())
+    }
+}
 
-    fn load_contents(path: &String) -> anyhow::Result<String> {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
+impl FromStr for Override {
+    type Err = anyhow::Error;
 
-        file.read_to_string(&mut contents)?;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected KEY=VALUE, got `{}`", s))?;
 
-        Ok(contents)
+        Ok(Self::new(
+            key.split('.').map(str::to_owned).collect(),
+            serde_yaml::from_str(value)?,
+        ))
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self::new(None, [0.0; 4], Default::default())
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{0}:{1}: {2}")]
+    Parse(usize, usize, String),
+    #[error("{0}")]
+    Value(String),
+    #[error("`{0}` must be {1}, using the default")]
+    OutOfRange(&'static str, &'static str),
+    #[error("font file `{0}` does not exist, using the default")]
+    MissingFont(String),
+    #[error("{0} imports itself")]
+    ImportCycle(String),
+}
+
+impl ConfigError {
+    /// Rewrites a YAML error as `line:column: message`, suggesting the closest known field
+    /// when a field is not recognized.
+    fn from_yaml(e: serde_yaml::Error) -> Self {
+        let message = e.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(end) => &message[..end],
+            None => &message,
+        };
+        let message = match message.find("unknown field `") {
+            Some(start) => {
+                // Nested fields are preceded by the path to their section, as in `font: `.
+                let section = &message[..start];
+                // The field comes first, followed by the expected ones, all in backticks.
+                let mut names = message[start..].split('`').skip(1).step_by(2);
+                let field = names.next().unwrap_or_default();
+                let suggestion = names
+                    .map(|name| (strsim::jaro_winkler(field, name), name))
+                    .filter(|(similarity, _)| *similarity > 0.8)
+                    .max_by(|a, b| a.0.total_cmp(&b.0));
+
+                match suggestion {
+                    Some((_, name)) => format!(
+                        "{}unknown field `{}`, did you mean `{}`",
+                        section, field, name
+                    ),
+                    None => format!("{}unknown field `{}`", section, field),
+                }
+            }
+            None => message.to_owned(),
+        };
+
+        match e.location() {
+            Some(location) => Self::Parse(location.line(), location.column(), message),
+            None => Self::Value(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new, empty directory for the files of the test called `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("foxterm-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn parse_error(yaml: &str) -> String {
+        serde_yaml::from_str::<Config>(yaml)
+            .map_err(ConfigError::from_yaml)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn unknown_fields_suggest_the_closest_one() {
+        assert_eq!(
+            parse_error("scrolback_lines: 3\n"),
+            "1:1: unknown field `scrolback_lines`, did you mean `scrollback_lines`"
+        );
+        assert_eq!(
+            parse_error("font:\n  sclae: 3\n"),
+            "2:3: font: unknown field `sclae`, did you mean `scale`"
+        );
+        assert_eq!(
+            parse_error("shell:\n  xyz: 3\n"),
+            "2:3: shell: unknown field `xyz`"
+        );
+    }
+
+    #[test]
+    fn other_errors_keep_their_location() {
+        assert_eq!(
+            parse_error("scrollback_lines: many\n"),
+            "1:19: scrollback_lines: invalid type: string \"many\", expected usize"
+        );
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let config: Config = serde_yaml::from_str("font:\n  scale: 28\n").unwrap();
+
+        assert_eq!(config.font.scale, 28.0);
+        assert_eq!(config.font.color, Font::default().color);
+        assert_eq!(config.scrollback_lines, DEFAULT_SCROLLBACK_LINES);
+    }
+
+    #[test]
+    fn values_out_of_range_fall_back_to_their_defaults() {
+        let mut config: Config = serde_yaml::from_str(
+            "bg_color: [2, 0, 0]\n\
+             font:\n  scale: -1\n  path: /nonexistent.ttf\n  bold: /nonexistent-bold.ttf\n\
+             colors:\n  cursor: [0, 0, 0, 3]\n  foreground: [0.5, 0.5, 0.5]\n",
+        )
+        .unwrap();
+        let warnings = config
+            .validate()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let default = Config::default();
+
+        assert_eq!(
+            warnings,
+            [
+                "`bg_color` must be between 0 and 1, using the default",
+                "`colors.cursor` must be between 0 and 1, using the default",
+                "`font.scale` must be greater than 0, using the default",
+                "font file `/nonexistent.ttf` does not exist, using the default",
+                "font file `/nonexistent-bold.ttf` does not exist, using the default",
+            ]
+        );
+        assert_eq!(config.bg_color, default.bg_color);
+        assert_eq!(config.font.scale, default.font.scale);
+        assert_eq!(config.font.path, None);
+        assert_eq!(config.font.bold, None);
+        assert_eq!(config.colors.cursor, None);
+        assert_eq!(config.colors.foreground, Some(Rgba([0.5, 0.5, 0.5, 1.0])));
+    }
+
+    #[test]
+    fn valid_configs_have_no_warnings() {
+        assert!(Config::default().validate().is_empty());
+    }
+
+    #[test]
+    fn parses_overrides() {
+        let o: Override = "font.scale=28".parse().unwrap();
+
+        assert_eq!(o.path, ["font", "scale"]);
+        assert_eq!(o.value, Value::from(28));
+
+        let o: Override = "shell.program=/bin/zsh".parse().unwrap();
+
+        assert_eq!(o.path, ["shell", "program"]);
+        assert_eq!(o.value, Value::from("/bin/zsh"));
+        assert!("font.scale".parse::<Override>().is_err());
+    }
+
+    #[test]
+    fn overrides_replace_values_and_create_sections() {
+        let mut value: Value =
+            serde_yaml::from_str("font:\n  scale: 20\n  color: '#ffffff'\n").unwrap();
+
+        "font.scale=28"
+            .parse::<Override>()
+            .unwrap()
+            .apply(&mut value)
+            .unwrap();
+        "shell.login=true"
+            .parse::<Override>()
+            .unwrap()
+            .apply(&mut value)
+            .unwrap();
+
+        let config: Config = serde_yaml::from_value(value.clone()).unwrap();
+
+        assert_eq!(config.font.scale, 28.0);
+        assert_eq!(config.font.color, Rgba([1.0; 4]));
+        assert!(config.shell.login);
+
+        let error = "font.scale.x=1"
+            .parse::<Override>()
+            .unwrap()
+            .apply(&mut value)
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "`x` is not a section");
+    }
+
+    #[test]
+    fn imports_merge_in_order_under_the_config() {
+        let dir = temp_dir("imports");
+
+        fs::write(
+            dir.join("base.yaml"),
+            "font:\n  scale: 10\n  color: '#ff0000'\nscrollback_lines: 5\n",
+        )
+        .unwrap();
+        fs::write(dir.join("extra.yaml"), "font:\n  scale: 20\n").unwrap();
+        fs::write(
+            dir.join(CONFIG_FILE_NAME),
+            "import: [base.yaml, extra.yaml]\nscrollback_lines: 7\n",
+        )
+        .unwrap();
+
+        let config = Config::from_file(&dir.join(CONFIG_FILE_NAME), &[]).unwrap();
+
+        assert_eq!(config.font.scale, 20.0);
+        assert_eq!(config.font.color, Rgba([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(config.scrollback_lines, 7);
+        assert_eq!(
+            config.files,
+            [
+                dir.join(CONFIG_FILE_NAME),
+                dir.join("base.yaml"),
+                dir.join("extra.yaml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_cycles_are_errors() {
+        let dir = temp_dir("import-cycle");
+
+        fs::write(dir.join("a.yaml"), "import: [b.yaml]\n").unwrap();
+        fs::write(dir.join("b.yaml"), "import: [a.yaml]\n").unwrap();
+
+        let error = Config::from_file(&dir.join("a.yaml"), &[]).unwrap_err();
+
+        assert!(error.to_string().ends_with("imports itself"), "{}", error);
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(
+            Rgba::from_hex("#ff8000"),
+            Some(Rgba([1.0, 128.0 / 255.0, 0.0, 1.0]))
+        );
+        assert_eq!(
+            Rgba::from_hex("#FF800033"),
+            Some(Rgba([1.0, 128.0 / 255.0, 0.0, 0.2]))
+        );
+        assert_eq!(Rgba::from_hex("ff8000"), None);
+        assert_eq!(Rgba::from_hex("#ff80"), None);
+        assert_eq!(Rgba::from_hex("#ff80001"), None);
+        assert_eq!(Rgba::from_hex("#gg8000"), None);
+        assert_eq!(Rgba::from_hex("#ff80é"), None);
+    }
+
+    #[test]
+    fn parses_colors_as_hex_or_lists() {
+        let colors: Colors = serde_yaml::from_str(
+            "foreground: '#102030'\nbackground: [0.5, 0.5, 0.5]\ncursor: [0, 0, 0, 0.5]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            colors.foreground.unwrap().to_rgb(),
+            Rgb::new(0x10, 0x20, 0x30)
+        );
+        assert_eq!(colors.background, Some(Rgba([0.5, 0.5, 0.5, 1.0])));
+        assert_eq!(colors.cursor, Some(Rgba([0.0, 0.0, 0.0, 0.5])));
+        assert!(serde_yaml::from_str::<Colors>("foreground: '#1020'\n").is_err());
+        assert!(serde_yaml::from_str::<Colors>("foreground: [1, 1]\n").is_err());
+        assert!(serde_yaml::from_str::<Colors>("foreground: [1, 1, 1, 1, 1]\n").is_err());
+    }
+
+    #[test]
+    fn themes_merge_under_the_colors_section() {
+        let dir = temp_dir("themes");
+
+        fs::create_dir(dir.join(THEMES_DIR_NAME)).unwrap();
+        fs::write(
+            dir.join(THEMES_DIR_NAME).join("dark.yaml"),
+            "foreground: '#ff0000'\nbackground: '#00ff00'\ncursor: '#0000ff'\nansi: ['#010101']\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join(CONFIG_FILE_NAME),
+            "theme: dark\ncolors:\n  background: '#ffffff'\n",
+        )
+        .unwrap();
+
+        let overrides = ["colors.cursor='#000000'".parse().unwrap()];
+        let config = Config::from_file(&dir.join(CONFIG_FILE_NAME), &overrides).unwrap();
+
+        assert_eq!(config.colors.foreground, Some(Rgba([1.0, 0.0, 0.0, 1.0])));
+        assert_eq!(config.colors.background, Some(Rgba([1.0; 4])));
+        assert_eq!(config.colors.cursor, Some(Rgba([0.0, 0.0, 0.0, 1.0])));
+        assert_eq!(config.palette()[0], Rgb::new(1, 1, 1));
+        assert_eq!(config.palette()[1], ANSI_COLORS[1]);
+        assert_eq!(
+            config.files,
+            [
+                dir.join(CONFIG_FILE_NAME),
+                dir.join(THEMES_DIR_NAME).join("dark.yaml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_theme_fields_point_into_the_theme() {
+        let dir = temp_dir("theme-error");
+
+        fs::create_dir(dir.join(THEMES_DIR_NAME)).unwrap();
+        fs::write(
+            dir.join(THEMES_DIR_NAME).join("dark.yaml"),
+            "forground: '#ff0000'\n",
+        )
+        .unwrap();
+        fs::write(dir.join(CONFIG_FILE_NAME), "theme: dark\n").unwrap();
+
+        let error = Config::from_file(&dir.join(CONFIG_FILE_NAME), &[]).unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", error),
+            format!(
+                "{}: 1:1: unknown field `forground`, did you mean `foreground`",
+                dir.join(THEMES_DIR_NAME).join("dark.yaml").display()
+            )
+        );
     }
 }
@@ -0,0 +1,236 @@
+use foxterm_core::performer::Mode;
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+const ESC: u8 = 0x1b;
+
+/// Encodes a key press that does not produce text on its own, such as a cursor or function
+/// key, into the bytes xterm sends for it. Returns `None` for keys that are sent as the text
+/// they produce instead.
+pub fn encode_key(key: VirtualKeyCode, mods: ModifiersState, mode: Mode) -> Option<Vec<u8>> {
+    use VirtualKeyCode::*;
+
+    let app_cursor = mode.contains(Mode::APP_CURSOR);
+    let app_keypad = mode.contains(Mode::APP_KEYPAD);
+
+    let bytes = match key {
+        Up => cursor_key(b'A', mods, app_cursor),
+        Down => cursor_key(b'B', mods, app_cursor),
+        Right => cursor_key(b'C', mods, app_cursor),
+        Left => cursor_key(b'D', mods, app_cursor),
+        Home => cursor_key(b'H', mods, app_cursor),
+        End => cursor_key(b'F', mods, app_cursor),
+        Insert => tilde_key(2, mods),
+        Delete => tilde_key(3, mods),
+        PageUp => tilde_key(5, mods),
+        PageDown => tilde_key(6, mods),
+        F1 => cursor_key(b'P', mods, true),
+        F2 => cursor_key(b'Q', mods, true),
+        F3 => cursor_key(b'R', mods, true),
+        F4 => cursor_key(b'S', mods, true),
+        F5 => tilde_key(15, mods),
+        F6 => tilde_key(17, mods),
+        F7 => tilde_key(18, mods),
+        F8 => tilde_key(19, mods),
+        F9 => tilde_key(20, mods),
+        F10 => tilde_key(21, mods),
+        F11 => tilde_key(23, mods),
+        F12 => tilde_key(24, mods),
+        Numpad0 | Numpad1 | Numpad2 | Numpad3 | Numpad4 | Numpad5 | Numpad6 | Numpad7 | Numpad8
+        | Numpad9
+            if app_keypad =>
+        {
+            ss3(b'p' + (key as u32 - Numpad0 as u32) as u8)
+        }
+        NumpadEnter if app_keypad => ss3(b'M'),
+        NumpadMultiply if app_keypad => ss3(b'j'),
+        NumpadAdd if app_keypad => ss3(b'k'),
+        NumpadComma if app_keypad => ss3(b'l'),
+        NumpadSubtract if app_keypad => ss3(b'm'),
+        NumpadDecimal if app_keypad => ss3(b'n'),
+        NumpadDivide if app_keypad => ss3(b'o'),
+        NumpadEquals if app_keypad => ss3(b'X'),
+        Return | NumpadEnter => meta(b"\r", mods),
+        Tab if mods.shift() => b"\x1b[Z".to_vec(),
+        Tab => meta(b"\t", mods),
+        Back if mods.ctrl() => meta(b"\x08", mods),
+        Back => meta(b"\x7f", mods),
+        Escape => meta(&[ESC], mods),
+        _ if mods.ctrl() => meta(&[control_code(key)?], mods),
+        _ => return None,
+    };
+
+    Some(bytes)
+}
+
+/// Encodes text typed by the user, prefixing it with `ESC` when Alt is held.
+pub fn encode_char(c: char, mods: ModifiersState) -> Vec<u8> {
+    let mut buf = [0; 4];
+
+    meta(c.encode_utf8(&mut buf).as_bytes(), mods)
+}
+
+/// The C0 control code sent for Ctrl with `key`.
+fn control_code(key: VirtualKeyCode) -> Option<u8> {
+    use VirtualKeyCode::*;
+
+    let code = match key {
+        A | B | C | D | E | F | G | H | I | J | K | L | M | N | O | P | Q | R | S | T | U | V
+        | W | X | Y | Z => key as u8 - A as u8 + 1,
+        Space | Key2 | At => 0x00,
+        LBracket | Key3 => ESC,
+        Backslash | Key4 => 0x1c,
+        RBracket | Key5 => 0x1d,
+        Key6 => 0x1e,
+        Slash | Minus | Key7 => 0x1f,
+        Key8 => 0x7f,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// The xterm modifier parameter, or `None` without modifiers.
+fn modifier_param(mods: ModifiersState) -> Option<u8> {
+    let param = 1
+        + mods.shift() as u8
+        + ((mods.alt() as u8) << 1)
+        + ((mods.ctrl() as u8) << 2)
+        + ((mods.logo() as u8) << 3);
+
+    (param > 1).then_some(param)
+}
+
+/// `ESC [ final`, `ESC O final` in application mode, or `ESC [ 1 ; mods final` when modified.
+fn cursor_key(final_byte: u8, mods: ModifiersState, application: bool) -> Vec<u8> {
+    match modifier_param(mods) {
+        Some(param) => format!("\x1b[1;{}{}", param, final_byte as char).into_bytes(),
+        None if application => ss3(final_byte),
+        None => vec![ESC, b'[', final_byte],
+    }
+}
+
+/// `ESC [ code ~`, or `ESC [ code ; mods ~` when modified.
+fn tilde_key(code: u8, mods: ModifiersState) -> Vec<u8> {
+    match modifier_param(mods) {
+        Some(param) => format!("\x1b[{};{}~", code, param).into_bytes(),
+        None => format!("\x1b[{}~", code).into_bytes(),
+    }
+}
+
+fn ss3(final_byte: u8) -> Vec<u8> {
+    vec![ESC, b'O', final_byte]
+}
+
+/// Prefixes `bytes` with `ESC` when Alt is held, which is how xterm sends Meta.
+fn meta(bytes: &[u8], mods: ModifiersState) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len() + 1);
+
+    if mods.alt() {
+        encoded.push(ESC);
+    }
+
+    encoded.extend_from_slice(bytes);
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VirtualKeyCode::*;
+
+    const NONE: ModifiersState = ModifiersState::empty();
+    const SHIFT: ModifiersState = ModifiersState::SHIFT;
+    const CTRL: ModifiersState = ModifiersState::CTRL;
+    const ALT: ModifiersState = ModifiersState::ALT;
+
+    #[test]
+    fn encodes_keys_like_xterm() {
+        let normal = Mode::empty();
+        let cursor = Mode::APP_CURSOR;
+        let keypad = Mode::APP_KEYPAD;
+        let cases: &[(VirtualKeyCode, ModifiersState, Mode, &[u8])] = &[
+            // Ctrl with letters and punctuation sends C0 codes.
+            (A, CTRL, normal, b"\x01"),
+            (C, CTRL, normal, b"\x03"),
+            (Z, CTRL, normal, b"\x1a"),
+            (Space, CTRL, normal, b"\x00"),
+            (LBracket, CTRL, normal, b"\x1b"),
+            (Backslash, CTRL, normal, b"\x1c"),
+            (Slash, CTRL, normal, b"\x1f"),
+            // Alt prefixes ESC.
+            (A, CTRL | ALT, normal, b"\x1b\x01"),
+            (Return, ALT, normal, b"\x1b\r"),
+            (Back, NONE, normal, b"\x7f"),
+            (Back, CTRL, normal, b"\x08"),
+            (Escape, NONE, normal, b"\x1b"),
+            // Cursor keys follow DECCKM unless they are modified.
+            (Up, NONE, normal, b"\x1b[A"),
+            (Left, NONE, normal, b"\x1b[D"),
+            (Home, NONE, normal, b"\x1b[H"),
+            (Up, NONE, cursor, b"\x1bOA"),
+            (End, NONE, cursor, b"\x1bOF"),
+            (Up, CTRL, normal, b"\x1b[1;5A"),
+            (Up, CTRL, cursor, b"\x1b[1;5A"),
+            (Right, SHIFT, normal, b"\x1b[1;2C"),
+            (Down, SHIFT | ALT, normal, b"\x1b[1;4B"),
+            // Function and editing keys.
+            (F1, NONE, normal, b"\x1bOP"),
+            (F4, NONE, normal, b"\x1bOS"),
+            (F1, SHIFT, normal, b"\x1b[1;2P"),
+            (F5, NONE, normal, b"\x1b[15~"),
+            (F6, NONE, normal, b"\x1b[17~"),
+            (F10, NONE, normal, b"\x1b[21~"),
+            (F11, NONE, normal, b"\x1b[23~"),
+            (F12, NONE, normal, b"\x1b[24~"),
+            (F12, CTRL, normal, b"\x1b[24;5~"),
+            (Delete, NONE, normal, b"\x1b[3~"),
+            (PageUp, NONE, normal, b"\x1b[5~"),
+            (Tab, NONE, normal, b"\t"),
+            (Tab, SHIFT, normal, b"\x1b[Z"),
+            // The keypad sends SS3 codes only in application mode.
+            (Numpad0, NONE, keypad, b"\x1bOp"),
+            (Numpad9, NONE, keypad, b"\x1bOy"),
+            (NumpadEnter, NONE, keypad, b"\x1bOM"),
+            (NumpadAdd, NONE, keypad, b"\x1bOk"),
+            (NumpadEnter, NONE, normal, b"\r"),
+        ];
+
+        for &(key, mods, mode, expected) in cases {
+            assert_eq!(
+                encode_key(key, mods, mode).as_deref(),
+                Some(expected),
+                "{:?} with {:?} in {:?}",
+                key,
+                mods,
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_text_keys_to_the_text_they_produce() {
+        assert_eq!(encode_key(A, NONE, Mode::empty()), None);
+        assert_eq!(encode_key(A, SHIFT, Mode::empty()), None);
+        assert_eq!(encode_key(Numpad1, NONE, Mode::empty()), None);
+        assert_eq!(encode_key(Numpad1, NONE, Mode::APP_CURSOR), None);
+    }
+
+    #[test]
+    fn encodes_text() {
+        assert_eq!(encode_char('a', NONE), b"a");
+        assert_eq!(encode_char('é', NONE), "é".as_bytes());
+        assert_eq!(encode_char('a', ALT), b"\x1ba");
+    }
+
+    #[test]
+    fn modifier_params() {
+        assert_eq!(modifier_param(NONE), None);
+        assert_eq!(modifier_param(SHIFT), Some(2));
+        assert_eq!(modifier_param(ALT), Some(3));
+        assert_eq!(modifier_param(SHIFT | ALT), Some(4));
+        assert_eq!(modifier_param(CTRL), Some(5));
+        assert_eq!(modifier_param(CTRL | SHIFT), Some(6));
+        assert_eq!(modifier_param(ModifiersState::LOGO), Some(9));
+    }
+}
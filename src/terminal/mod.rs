@@ -1,117 +1,315 @@
 pub mod config;
-pub mod drawable;
-pub mod pty;
+pub mod input;
+pub mod watcher;
 
-use crate::loaded_font::{chr::Chr, LoadedFont};
-use cgmath::{Array, Vector2, Vector4, Zero};
+use crate::loaded_font::metrics::CellMetrics;
 use config::Config;
 use crossbeam::channel::{self, Receiver, Sender};
-use drawable::{Drawable, RenderItem};
-use pty::Pty;
+use foxterm_core::{
+    color::{Color, Palette},
+    grid::{Cell, Flags},
+    performer::Performer,
+    pty::Pty,
+};
+use nix::pty::Winsize;
 use std::{
-    env,
     sync::{Arc, RwLock},
     thread,
 };
-use vte::{Params, Parser, Perform};
-use winit::event::VirtualKeyCode;
-use winit_input_helper::{TextChar, WinitInputHelper};
+use vte::Parser;
+use winit::{
+    dpi::PhysicalSize,
+    event::{ModifiersState, VirtualKeyCode},
+    event_loop::EventLoopProxy,
+};
+use winit_input_helper::WinitInputHelper;
+
+pub const DEFAULT_ROWS: usize = 24;
+pub const DEFAULT_COLS: usize = 80;
+
+/// The number of lines scrolled per mouse wheel step.
+const SCROLL_MULTIPLIER: f32 = 3.0;
+
+/// How much the foreground of dim (SGR 2) text is darkened.
+const DIM_FACTOR: f32 = 2.0 / 3.0;
+
+/// Sent to the event loop from the terminal's threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalEvent {
+    /// Output from the PTY changed the screen, so it needs to be redrawn.
+    Wakeup,
+    /// The shell exited with the given code.
+    Exit(i32),
+    /// The config file changed on disk.
+    ConfigChanged,
+    /// The fonts on the system were indexed, so characters missing so far may be found now.
+    FontsIndexed,
+}
 
 pub struct Terminal {
     pub config: Config,
     pub pty: Arc<Pty>,
-    pub screen: Arc<RwLock<Vec<Drawable>>>,
+    pub performer: Arc<RwLock<Performer>>,
+    pub palette: Palette,
+    /// The size of the cells of the grid, measured from the font.
+    pub cell: CellMetrics,
 }
 
 impl Terminal {
-    pub fn new(config: Config, pty: Arc<Pty>, screen: Arc<RwLock<Vec<Drawable>>>) -> Self {
+    pub fn new(
+        config: Config,
+        pty: Arc<Pty>,
+        performer: Arc<RwLock<Performer>>,
+        palette: Palette,
+        cell: CellMetrics,
+    ) -> Self {
         Self {
             config,
             pty,
-            screen,
+            performer,
+            palette,
+            cell,
+        }
+    }
+
+    pub fn init(config: Config, cell: CellMetrics) -> anyhow::Result<Self> {
+        let winsize = Self::winsize(&cell, DEFAULT_ROWS, DEFAULT_COLS);
+        let pty = Pty::spawn(&config.shell.command(), &winsize)?;
+        let performer = Performer::default(DEFAULT_ROWS, DEFAULT_COLS, config.scrollback_lines);
+        let palette = config.palette();
+
+        Ok(Self::new(
+            config,
+            pty,
+            Arc::new(RwLock::new(performer)),
+            palette,
+            cell,
+        ))
+    }
+
+    /// The window size that fits the grid exactly.
+    pub fn window_size(&self) -> PhysicalSize<u32> {
+        let grid = &self.performer.read().unwrap().grid;
+
+        PhysicalSize::new(
+            (grid.cols as f32 * self.cell.size.x) as u32,
+            (grid.rows as f32 * self.cell.size.y) as u32,
+        )
+    }
+
+    /// The number of rows and columns of cells that fit in a window of the given size.
+    pub fn grid_size(&self, size: PhysicalSize<u32>) -> (usize, usize) {
+        (
+            ((size.height as f32 / self.cell.size.y) as usize).max(1),
+            ((size.width as f32 / self.cell.size.x) as usize).max(1),
+        )
+    }
+
+    /// Whether the screen changed since it was last drawn.
+    pub fn is_damaged(&self) -> bool {
+        self.performer.read().unwrap().grid.is_damaged()
+    }
+
+    /// Replaces the config and the cell size of the font it loads, redrawing everything and
+    /// fitting the grid to `size` again in case the cell size changed.
+    pub fn set_config(
+        &mut self,
+        config: Config,
+        cell: CellMetrics,
+        size: PhysicalSize<u32>,
+    ) -> anyhow::Result<()> {
+        self.palette = config.palette();
+        self.config = config;
+        self.cell = cell;
+        self.performer.write().unwrap().grid.damage_all();
+
+        self.resize(size)
+    }
+
+    /// Resizes the grid to fit a window of the given size and tells the shell about it.
+    pub fn resize(&self, size: PhysicalSize<u32>) -> anyhow::Result<()> {
+        let (rows, cols) = self.grid_size(size);
+        let mut performer = self.performer.write().unwrap();
+
+        if (rows, cols) != (performer.grid.rows, performer.grid.cols) {
+            performer.resize(rows, cols);
+
+            self.pty.resize(&Self::winsize(&self.cell, rows, cols))?;
         }
+
+        Ok(())
     }
 
-    pub fn init() -> anyhow::Result<Option<Self>> {
-        match Pty::spawn(env::var("SHELL").unwrap())? {
-            Some(pty) => {
-                let config = Config::default_from_file()?;
+    fn winsize(cell: &CellMetrics, rows: usize, cols: usize) -> Winsize {
+        Winsize {
+            ws_row: rows as u16,
+            ws_col: cols as u16,
+            ws_xpixel: (cols as f32 * cell.size.x) as u16,
+            ws_ypixel: (rows as f32 * cell.size.y) as u16,
+        }
+    }
 
-                Ok(Some(Self::new(
-                    config,
-                    pty,
-                    Arc::new(RwLock::new(Vec::new())),
-                )))
+    /// The foreground and background colours a cell is drawn with, after applying its
+    /// attributes.
+    pub fn cell_colors(&self, cell: &Cell) -> ([f32; 4], [f32; 4]) {
+        let fg = match cell.fg {
+            Color::Indexed(i @ 0..=7) if cell.flags.contains(Flags::BOLD) => Color::Indexed(i + 8),
+            fg => fg,
+        };
+        let mut fg = self.resolve_color(fg);
+        let mut bg = self.resolve_color(cell.bg);
+
+        if cell.flags.contains(Flags::DIM) {
+            for c in &mut fg[..3] {
+                *c *= DIM_FACTOR;
             }
-            None => Ok(None),
         }
+
+        if cell.flags.contains(Flags::INVERSE) {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+
+        if cell.flags.contains(Flags::HIDDEN) {
+            fg = bg;
+        }
+
+        (fg, bg)
     }
 
-    pub fn update_pty(
+    fn resolve_color(&self, color: Color) -> [f32; 4] {
+        match color {
+            Color::Foreground => self.config.foreground(),
+            Color::Background => self.config.background(),
+            Color::Indexed(i) => self.palette[i].to_rgba(1.0),
+            Color::Spec(rgb) => rgb.to_rgba(1.0),
+        }
+    }
+
+    /// Scrolls the viewport through the history with the mouse wheel and Shift+PageUp/PageDown.
+    pub fn update_viewport(&self, input: &WinitInputHelper) {
+        let mut performer = self.performer.write().unwrap();
+        let page = performer.grid.rows as isize;
+        let mut delta = (input.scroll_diff() * SCROLL_MULTIPLIER).round() as isize;
+
+        if input.held_shift() {
+            if input.key_pressed(VirtualKeyCode::PageUp) {
+                delta += page;
+            } else if input.key_pressed(VirtualKeyCode::PageDown) {
+                delta -= page;
+            }
+        }
+
+        if delta != 0 {
+            performer.grid.scroll_display(delta);
+        }
+    }
+
+    /// Sends the encoding of a pressed key to the shell. Returns whether the key was handled,
+    /// in which case the text it produces must not be sent as well.
+    pub fn key_input(
         &self,
         sender: &Sender<Vec<u8>>,
-        input: &WinitInputHelper,
-    ) -> anyhow::Result<()> {
-        let mut text = input
-            .text()
-            .into_iter()
-            .map(|c| match c {
-                TextChar::Char(c) => c as u8,
-                #[allow(clippy::char_lit_as_u8)]
-                TextChar::Back => '\u{8}' as u8,
-            })
-            .collect::<Vec<_>>();
-
-        if input.key_pressed(VirtualKeyCode::Return) {
-            text.push(b'\r');
-        } else if input.key_pressed(VirtualKeyCode::Tab) {
-            text.push(b'\t');
-        } else if input.key_pressed(VirtualKeyCode::LControl)
-            || input.key_pressed(VirtualKeyCode::RControl)
-        {
-            text.push(b'^');
+        key: VirtualKeyCode,
+        mods: ModifiersState,
+    ) -> anyhow::Result<bool> {
+        let mut performer = self.performer.write().unwrap();
+
+        if mods.shift() && matches!(key, VirtualKeyCode::PageUp | VirtualKeyCode::PageDown) {
+            return Ok(true);
         }
 
-        sender.send(text)?;
+        match input::encode_key(key, mods, performer.mode) {
+            Some(bytes) => {
+                performer.grid.scroll_to_bottom();
+                sender.send(bytes)?;
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Sends text typed by the user to the shell.
+    pub fn received_char(
+        &self,
+        sender: &Sender<Vec<u8>>,
+        c: char,
+        mods: ModifiersState,
+    ) -> anyhow::Result<()> {
+        self.performer.write().unwrap().grid.scroll_to_bottom();
+
+        sender.send(input::encode_char(c, mods))?;
 
         Ok(())
     }
 
-    pub fn spawn_reader(&self, font: Arc<LoadedFont>) -> Arc<RwLock<Performer>> {
+    /// Spawns the thread feeding PTY output to the performer, waking the event loop through
+    /// `proxy` whenever the output damaged the screen. Once the PTY is closed the child is
+    /// reaped and its exit code is sent as [`TerminalEvent::Exit`].
+    pub fn spawn_reader(&self, proxy: EventLoopProxy<TerminalEvent>) {
         let pty = self.pty.clone();
-        let screen = self.screen.clone();
-        let performer = Arc::new(RwLock::new(Performer::default(font, screen)));
+        let performer = self.performer.clone();
 
-        {
-            let performer = performer.clone();
+        thread::spawn(move || {
+            // Sequences may be split across reads, so the parser has to outlive each of them.
+            let mut parser = Parser::new();
 
-            thread::spawn(move || loop {
+            loop {
                 match pty.read() {
+                    Ok(buf) if buf.is_empty() => break,
                     Ok(buf) => {
-                        let mut parser = Parser::new();
                         let mut performer = performer.write().unwrap();
 
                         for u in buf {
                             performer.advance_parser(&mut parser, u);
                         }
+
+                        if performer.grid.is_damaged()
+                            && proxy.send_event(TerminalEvent::Wakeup).is_err()
+                        {
+                            break;
+                        }
                     }
+                    // Linux reports EIO once the last process holding the other side exited.
                     Err(e) => match e.downcast_ref::<nix::errno::Errno>() {
-                        Some(nix::errno::Errno::EBADF) => break,
+                        Some(nix::errno::Errno::EBADF) | Some(nix::errno::Errno::EIO) => break,
                         _ => {
                             println!("Error on read: {:?}", e);
                         }
                     },
                 }
-            });
+            }
+
+            match pty.wait() {
+                Ok(code) => {
+                    let _ = proxy.send_event(TerminalEvent::Exit(code));
+                }
+                Err(e) => println!("Failed to wait for the shell: {:?}", e),
+            }
+        });
+    }
+
+    /// Prints a notice that the shell exited with `code` below its last output.
+    pub fn show_exit(&self, code: i32) {
+        let grid = &mut self.performer.write().unwrap().grid;
+
+        grid.scroll_to_bottom();
+        grid.cursor.template = Cell::default();
+
+        if grid.cursor.col > 0 {
+            grid.carriage_return();
+            grid.linefeed();
         }
 
-        performer
+        for c in format!("process exited with code {}", code).chars() {
+            grid.write(c);
+        }
     }
 
     pub fn spawn_writer(&self) -> Sender<Vec<u8>> {
         let (sender, receiver): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = channel::unbounded();
         let pty = self.pty.clone();
- 
+
         thread::spawn(move || {
             while let Ok(content) = receiver.recv() {
                 if let Err(e) = pty.write(&content) {
@@ -128,164 +326,3 @@ impl Terminal {
         sender
     }
 }
-
-pub struct Performer {
-    pub font: Arc<LoadedFont>,
-    pub screen: Arc<RwLock<Vec<Drawable>>>,
-    pub color: Vector4<f32>,
-    pub pos: Vector2<f32>,
-}
-
-impl Performer {
-    pub fn new(
-        font: Arc<LoadedFont>,
-        screen: Arc<RwLock<Vec<Drawable>>>,
-        color: Vector4<f32>,
-        pos: Vector2<f32>,
-    ) -> Self {
-        Self {
-            font,
-            screen,
-            color,
-            pos,
-        }
-    }
-
-    pub fn default(font: Arc<LoadedFont>, screen: Arc<RwLock<Vec<Drawable>>>) -> Self {
-        Self::new(font, screen, Vector4::zero(), Vector2::from_value(-1.0))
-    }
-
-    fn add_chr(&mut self, chr: Arc<Chr>) {
-        let mut screen = self.screen.write().unwrap();
-
-        self.pos.x += chr.bearing.x;
-
-        let mut pos = self.pos;
-
-        pos.y -= chr.bearing.y;
-
-        screen.push(Drawable::new(RenderItem::Chr(chr.clone()), pos));
-
-        self.pos.x += chr.dimensions.x;
-
-        update_pos(&mut self.pos, self.font.scale, &mut screen)
-    }
-
-    fn add_space(&mut self) {
-        let mut screen = self.screen.write().unwrap();
-
-        screen.push(Drawable::new(RenderItem::Space, self.pos));
-
-        self.pos.x += self.font.scale / 2.0;
-
-        update_pos(&mut self.pos, self.font.scale, &mut screen)
-    }
-
-    fn advance_parser(&mut self, parser: &mut Parser, u: u8) {
-        if u == 8 {
-            let mut screen = self.screen.write().unwrap();
-            let min_pos = {
-                let mut min_pos = self.pos - Vector2::new(self.font.scale / 2.0, 0.0);
-
-                update_pos(&mut min_pos, self.font.scale, &mut screen);
-
-                min_pos
-            };
-
-            for i in 0..screen.len() {
-                if screen[i].pos.y == min_pos.y && screen[i].pos.x >= min_pos.x {
-                    self.pos = screen[i].pos;
-
-                    screen.remove(i);
-
-                    break;
-                }
-            }
-
-            update_pos(&mut self.pos, self.font.scale, &mut screen);
-        } else if u == b' ' {
-            self.add_space();
-
-            update_pos(
-                &mut self.pos,
-                self.font.scale,
-                &mut self.screen.write().unwrap(),
-            )
-        } else {
-            parser.advance(self, u);
-        }
-    }
-}
-
-impl Perform for Performer {
-    fn print(&mut self, c: char) {
-        if let Some(chr) = self.font.get_chr_by_id(c as u8) {
-            self.add_chr(chr);
-        }
-    }
-
-    fn csi_dispatch(
-        &mut self,
-        params: &Params,
-        _intermediates: &[u8],
-        _ignore: bool,
-        action: char,
-    ) {
-        match action {
-            'K' => {
-                if let Some([0] | []) = params.iter().next() {
-                    self.pos.x = 1.0 + self.font.scale / 2.0;
-                }
-            }
-            'C' => match params.iter().next() {
-                Some([0] | []) => {
-                    self.pos.x += self.font.scale / 2.0;
-                }
-                Some([n]) => {
-                    self.pos.x += self.font.scale / 2.0 * *n as f32;
-                }
-                _ => {}
-            },
-            _ => {}
-        }
-
-        update_pos(
-            &mut self.pos,
-            self.font.scale,
-            &mut self.screen.write().unwrap(),
-        )
-    }
-}
-
-fn update_x(pos: &mut Vector2<f32>, scale: f32) {
-    if pos.x > 1.0 - scale / 2.0 {
-        *pos = Vector2::new(
-            -2.0 + pos.x + scale / 2.0,
-            pos.y + scale * (pos.x / 2.0).ceil(),
-        );
-    } else if pos.x < -1.0 {
-        *pos = Vector2::new(
-            2.0 + pos.x - scale / 2.0,
-            pos.y - scale * (pos.x / -2.0).ceil(),
-        );
-    }
-}
-
-fn update_y(pos: &mut Vector2<f32>, scale: f32, screen: &mut Vec<Drawable>) {
-    if pos.y > 1.0 - scale {
-        let dif = scale * (pos.y / (1.0 - scale).ceil());
-
-        pos.y -= dif;
-
-        screen.retain_mut(|d| {
-            d.pos.y -= dif;
-
-            d.pos.y > -1.0
-        });
-    }
-}
-
-fn update_pos(pos: &mut Vector2<f32>, scale: f32, screen: &mut Vec<Drawable>) {
-    update_x(pos, scale);
-    update_y(pos, scale, screen);
-}
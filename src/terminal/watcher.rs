@@ -0,0 +1,43 @@
+use super::TerminalEvent;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::{fs, path::PathBuf};
+use winit::event_loop::EventLoopProxy;
+
+/// Watches the config files at `paths`, sending [`TerminalEvent::ConfigChanged`] whenever one
+/// of them is written or replaced. Their directories are watched rather than the files
+/// themselves since editors often save by renaming a new file over the old one. Watching stops
+/// when the returned watcher is dropped.
+pub fn watch_config(
+    paths: &[PathBuf],
+    proxy: EventLoopProxy<TerminalEvent>,
+) -> anyhow::Result<RecommendedWatcher> {
+    let paths = paths
+        .iter()
+        .map(fs::canonicalize)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut dirs = paths
+        .iter()
+        .map(|path| path.parent().unwrap().to_owned())
+        .collect::<Vec<_>>();
+
+    dirs.sort();
+    dirs.dedup();
+
+    let mut watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) => {
+                if (event.kind.is_create() || event.kind.is_modify())
+                    && event.paths.iter().any(|path| paths.contains(path))
+                {
+                    let _ = proxy.send_event(TerminalEvent::ConfigChanged);
+                }
+            }
+            Err(e) => eprintln!("Error watching config: {:?}", e),
+        })?;
+
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(watcher)
+}